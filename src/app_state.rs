@@ -0,0 +1,329 @@
+use std::time::Duration;
+
+use bevy::{asset::LoadState, prelude::*};
+use strum::{EnumCount, IntoEnumIterator};
+
+use crate::{
+    board::{
+        Board, BoardGroup, BoardUpdateSystems, GameEnded, game_mode::GameMode, sound::SoundAssets,
+        stats::GameSummary, tile_assets::Skin,
+    },
+    music::MusicTracks,
+};
+
+const COUNTDOWN_TIME: Duration = Duration::from_secs(3);
+
+pub struct AppStatePlugin;
+
+impl Plugin for AppStatePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<AppState>()
+            .init_resource::<SelectedMode>()
+            .configure_sets(
+                FixedUpdate,
+                BoardUpdateSystems.run_if(in_state(AppState::Playing)),
+            )
+            .add_systems(OnEnter(AppState::Loading), spawn_loading_screen)
+            .add_systems(OnEnter(AppState::Title), spawn_title_screen)
+            .add_systems(OnEnter(AppState::ModeSelect), spawn_mode_select)
+            .add_systems(
+                OnEnter(AppState::Countdown),
+                (pause_simulation, spawn_countdown),
+            )
+            .add_systems(OnExit(AppState::Countdown), resume_simulation)
+            .add_systems(
+                OnEnter(AppState::Paused),
+                (pause_simulation, hide_boards, spawn_pause_screen),
+            )
+            .add_systems(OnExit(AppState::Paused), (resume_simulation, show_boards))
+            .add_systems(OnEnter(AppState::Results), spawn_results_screen)
+            .add_systems(OnExit(AppState::Results), despawn_boards)
+            .add_systems(
+                Update,
+                (
+                    check_loading.run_if(in_state(AppState::Loading)),
+                    apply_title_input.run_if(in_state(AppState::Title)),
+                    (apply_mode_select_input, update_mode_select_text)
+                        .chain()
+                        .run_if(in_state(AppState::ModeSelect)),
+                    update_countdown.run_if(in_state(AppState::Countdown)),
+                    toggle_pause.run_if(in_state(AppState::Playing).or(in_state(AppState::Paused))),
+                    check_games_ended.run_if(in_state(AppState::Playing)),
+                    apply_results_input.run_if(in_state(AppState::Results)),
+                ),
+            );
+    }
+}
+
+#[derive(States, Default, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AppState {
+    /// Waits for every texture and sound to load, since boards can't be spawned without them.
+    #[default]
+    Loading,
+    /// An asset failed to load. There's no way out but to fix the files and restart.
+    LoadFailed,
+    Title,
+    ModeSelect,
+    /// Boards are spawned but the simulation is paused until the countdown ends.
+    Countdown,
+    Playing,
+    Paused,
+    Results,
+}
+
+/// The mode boards are spawned with when a game starts.
+#[derive(Resource, Default)]
+pub struct SelectedMode(pub GameMode);
+
+#[derive(Component)]
+struct ModeSelectMenu {
+    selected: usize,
+}
+
+#[derive(Component)]
+struct Countdown(Timer);
+
+fn get_confirm_pressed(keys: &ButtonInput<KeyCode>, gamepads: &Query<&Gamepad>) -> bool {
+    keys.just_pressed(KeyCode::Enter)
+        || gamepads
+            .iter()
+            .any(|gamepad| gamepad.just_pressed(GamepadButton::South))
+}
+
+fn spawn_screen_text(commands: &mut Commands, state: AppState, text: impl Into<String>) -> Entity {
+    commands
+        .spawn((
+            Name::new("ScreenText"),
+            Text2d::new(text),
+            TextLayout::new_with_justify(Justify::Center),
+            Transform::from_xyz(0.0, 0.0, 10.0),
+            DespawnOnExit(state),
+        ))
+        .id()
+}
+
+// ========== Systems ==========
+
+fn spawn_loading_screen(mut commands: Commands) {
+    spawn_screen_text(&mut commands, AppState::Loading, "Loading...");
+}
+
+fn check_loading(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    skin: Res<Skin>,
+    sounds: Res<SoundAssets>,
+    music: Res<MusicTracks>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let handles: Vec<UntypedHandle> = skin
+        .get_handles()
+        .into_iter()
+        .chain(sounds.0.values().cloned().map(Handle::untyped))
+        .chain(music.0.values().cloned().map(Handle::untyped))
+        .collect();
+
+    let errors: Vec<String> = handles
+        .iter()
+        .filter_map(|handle| match asset_server.load_state(handle) {
+            LoadState::Failed(error) => Some(error.to_string()),
+            _ => None,
+        })
+        .collect();
+    if !errors.is_empty() {
+        for error in errors.iter() {
+            error!("{error}");
+        }
+        spawn_screen_text(
+            &mut commands,
+            AppState::LoadFailed,
+            format!("Failed to load assets\n\n{}", errors.join("\n")),
+        );
+        next_state.set(AppState::LoadFailed);
+        return;
+    }
+
+    let loaded = handles
+        .iter()
+        .all(|handle| asset_server.is_loaded_with_dependencies(handle));
+    if skin.is_applied() && loaded {
+        next_state.set(AppState::Title);
+    }
+}
+
+fn spawn_title_screen(mut commands: Commands) {
+    spawn_screen_text(
+        &mut commands,
+        AppState::Title,
+        "Tetrominoes\n\nPress Enter to start\nF1 controls  F2 skin",
+    );
+}
+
+fn apply_title_input(
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if get_confirm_pressed(&keys, &gamepads) {
+        next_state.set(AppState::ModeSelect);
+    }
+}
+
+fn spawn_mode_select(mut commands: Commands, selected_mode: Res<SelectedMode>) {
+    let entity = spawn_screen_text(&mut commands, AppState::ModeSelect, "");
+    let selected = GameMode::iter()
+        .position(|mode| mode == selected_mode.0)
+        .unwrap_or_default();
+    commands.entity(entity).insert(ModeSelectMenu { selected });
+}
+
+fn apply_mode_select_input(
+    mut menu: Single<&mut ModeSelectMenu>,
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    mut selected_mode: ResMut<SelectedMode>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let up = keys.just_pressed(KeyCode::ArrowUp)
+        || gamepads
+            .iter()
+            .any(|gamepad| gamepad.just_pressed(GamepadButton::DPadUp));
+    let down = keys.just_pressed(KeyCode::ArrowDown)
+        || gamepads
+            .iter()
+            .any(|gamepad| gamepad.just_pressed(GamepadButton::DPadDown));
+
+    if up {
+        menu.selected = (menu.selected + GameMode::COUNT - 1) % GameMode::COUNT;
+    } else if down {
+        menu.selected = (menu.selected + 1) % GameMode::COUNT;
+    } else if get_confirm_pressed(&keys, &gamepads) {
+        selected_mode.0 = GameMode::iter()
+            .nth(menu.selected)
+            .expect("Selected index is always in range");
+        next_state.set(AppState::Countdown);
+    } else if keys.just_pressed(KeyCode::Escape) {
+        next_state.set(AppState::Title);
+    }
+}
+
+fn update_mode_select_text(menu: Single<(&ModeSelectMenu, &mut Text2d), Changed<ModeSelectMenu>>) {
+    let (menu, mut text) = menu.into_inner();
+    let modes: Vec<String> = GameMode::iter()
+        .enumerate()
+        .map(|(index, mode)| {
+            if index == menu.selected {
+                format!("> {mode} <")
+            } else {
+                mode.to_string()
+            }
+        })
+        .collect();
+    text.0 = format!("Select Mode\n\n{}", modes.join("\n"));
+}
+
+fn pause_simulation(mut time: ResMut<Time<Virtual>>) {
+    time.pause();
+}
+
+fn resume_simulation(mut time: ResMut<Time<Virtual>>) {
+    time.unpause();
+}
+
+fn spawn_countdown(mut commands: Commands) {
+    let entity = spawn_screen_text(&mut commands, AppState::Countdown, "");
+    commands
+        .entity(entity)
+        .insert(Countdown(Timer::new(COUNTDOWN_TIME, TimerMode::Once)));
+}
+
+/// Runs on real time, since virtual time is paused during the countdown.
+fn update_countdown(
+    countdown: Single<(&mut Countdown, &mut Text2d)>,
+    time: Res<Time<Real>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let (mut countdown, mut text) = countdown.into_inner();
+    if countdown.0.tick(time.delta()).just_finished() {
+        next_state.set(AppState::Playing);
+    }
+    text.0 = countdown.0.remaining_secs().ceil().max(1.0).to_string();
+}
+
+fn toggle_pause(
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let pressed = keys.just_pressed(KeyCode::KeyP)
+        || gamepads
+            .iter()
+            .any(|gamepad| gamepad.just_pressed(GamepadButton::Start));
+    if !pressed {
+        return;
+    }
+
+    match state.get() {
+        AppState::Playing => next_state.set(AppState::Paused),
+        AppState::Paused => next_state.set(AppState::Playing),
+        _ => {}
+    }
+}
+
+fn hide_boards(mut groups: Query<&mut Visibility, With<BoardGroup>>) {
+    for mut visibility in groups.iter_mut() {
+        *visibility = Visibility::Hidden;
+    }
+}
+
+fn show_boards(mut groups: Query<&mut Visibility, With<BoardGroup>>) {
+    for mut visibility in groups.iter_mut() {
+        *visibility = Visibility::Inherited;
+    }
+}
+
+fn spawn_pause_screen(mut commands: Commands) {
+    spawn_screen_text(
+        &mut commands,
+        AppState::Paused,
+        "Paused\n\nPress P to resume",
+    );
+}
+
+fn check_games_ended(
+    boards: Query<Has<GameEnded>, With<Board>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if !boards.is_empty() && boards.iter().all(|ended| ended) {
+        next_state.set(AppState::Results);
+    }
+}
+
+fn spawn_results_screen(mut commands: Commands) {
+    let entity = spawn_screen_text(&mut commands, AppState::Results, "Enter: title    R: retry");
+    commands
+        .entity(entity)
+        .insert(Transform::from_xyz(0.0, -320.0, 10.0));
+}
+
+fn apply_results_input(
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if keys.just_pressed(KeyCode::KeyR) {
+        next_state.set(AppState::Countdown);
+    } else if get_confirm_pressed(&keys, &gamepads) {
+        next_state.set(AppState::Title);
+    }
+}
+
+fn despawn_boards(
+    mut commands: Commands,
+    entities: Query<Entity, Or<(With<BoardGroup>, With<GameSummary>)>>,
+) {
+    for entity in entities.iter() {
+        commands.entity(entity).despawn();
+    }
+}
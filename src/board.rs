@@ -1,30 +1,54 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, time::Duration};
 
 use bevy::prelude::*;
 use bevy_shuffle_bag::ShuffleBag;
 use leafwing_input_manager::prelude::ActionState;
 use rand::{Rng, seq::SliceRandom};
-use strum::IntoEnumIterator;
+use strum::{EnumCount, IntoEnumIterator};
 
-mod board_config;
+pub mod board_config;
+mod editor;
+pub mod fumen;
+pub mod game_mode;
 mod ghost_tile;
 pub mod hold_display;
+mod hud;
+mod input_buffer;
+mod layout;
 mod line_clear;
 mod outline;
+mod personal_best;
+pub mod piece_sequence;
 pub mod placed_tile;
 pub mod queue_display;
+pub mod sound;
+mod spin;
+pub mod stats;
 mod tetromino_data;
 mod tetromino_tile;
 pub mod tile_assets;
 
 use crate::{
     board::{
-        board_config::BoardConfig,
+        board_config::{
+            BoardConfig, BoardConfigPlugin, HoldMode, LineClearGravity, LockMode, SoftDropFactor,
+        },
+        editor::BoardEditorPlugin,
+        fumen::{FumenPlugin, FumenReplay},
+        game_mode::{GameMode, GameModePlugin},
         ghost_tile::{GhostTile, GhostTilePlugin, clear_ghost_tiles, spawn_ghost_tiles},
         hold_display::{HoldDisplay, HoldDisplayPlugin},
-        line_clear::LineClearPlugin,
-        placed_tile::PlacedTile,
-        queue_display::{QueueDisplay, QueueDisplayPlugin},
+        hud::{HudPlugin, ScoreHud, TimerHud},
+        input_buffer::{InputBuffer, InputBufferPlugin},
+        layout::LayoutPlugin,
+        line_clear::{ClearedRows, LineClearPlugin, get_fallen_positions},
+        personal_best::PersonalBestPlugin,
+        piece_sequence::PieceSequence,
+        placed_tile::{PlacedTile, PlacedTileKind, spawn_placed_tile},
+        queue_display::{QueueDisplay, QueueDisplayPlugin, get_queue_display_size},
+        sound::SoundPlugin,
+        spin::{Spin, get_t_spin},
+        stats::{BoardStats, StatsPlugin},
         tetromino_data::{
             TetrominoKind, TetrominoRotation, get_tetromino_shape, get_tetromino_start_piece,
             get_tetromino_wall_kicks,
@@ -32,13 +56,20 @@ use crate::{
         tetromino_tile::{
             TetrominoTile, TetrominoTilePlugin, clear_tetromino_tiles, spawn_tetromino_tiles,
         },
-        tile_assets::{TileAssets, TileImages, TileOutlineImages},
+        tile_assets::{
+            GarbageTileImage, GhostTileImages, TileAssets, TileImages, TileOutlineImages,
+        },
     },
-    input::{Action, get_board_input_map},
+    input::{Action, get_board_input_map, get_editor_input_map},
     rng::RandomSource,
     tiles::{Tile, TileUpdateSystems, Tilemap},
 };
 
+/// Rows per tick a piece falls on its own.
+const GRAVITY: f32 = 0.05;
+/// Buffer rows drawn above the board, so pieces can be seen as they spawn.
+const VISIBLE_BUFFER_HEIGHT: u32 = 2;
+
 pub struct BoardPlugin;
 
 impl Plugin for BoardPlugin {
@@ -50,7 +81,17 @@ impl Plugin for BoardPlugin {
             TileAssets,
             HoldDisplayPlugin,
             QueueDisplayPlugin,
+            BoardEditorPlugin,
+            FumenPlugin,
+            StatsPlugin,
+            GameModePlugin,
+            PersonalBestPlugin,
+            BoardConfigPlugin,
+            InputBufferPlugin,
+            HudPlugin,
+            SoundPlugin,
         ))
+        .add_plugins(LayoutPlugin)
         .add_systems(
             FixedUpdate,
             (
@@ -60,6 +101,7 @@ impl Plugin for BoardPlugin {
                     apply_shift,
                     apply_auto_shift,
                     apply_soft_drop,
+                    apply_sonic_drop,
                     apply_hard_drop,
                     apply_gravity,
                     apply_rotation,
@@ -68,11 +110,14 @@ impl Plugin for BoardPlugin {
                     apply_placement,
                     place_tetrominos,
                     spawn_next_tetrominos,
+                    fill_queues,
                     spawn_tetrominos,
+                    apply_initial_rotation,
                 )
                     .chain()
                     .in_set(BoardUpdateSystems),
                 remove_skip_update.in_set(RemoveSkipUpdateSystems),
+                apply_game_ended_skip_update.in_set(AddSkipUpdateSystems),
             ),
         )
         .configure_sets(
@@ -86,7 +131,12 @@ impl Plugin for BoardPlugin {
         )
         .add_message::<HoldPieceChanged>()
         .add_message::<TetrominoQueueChanged>()
+        .add_message::<TetrominoMoved>()
         .add_message::<PlaceTetromino>()
+        .add_message::<TetrominoPlaced>()
+        .add_message::<LinesCleared>()
+        .add_message::<LinesCollapsed>()
+        .add_message::<GameOver>()
         .add_message::<SpawnNextTetromino>()
         .add_message::<SpawnTetromino>();
     }
@@ -95,7 +145,7 @@ impl Plugin for BoardPlugin {
 pub type TetrominoQueue = VecDeque<TetrominoKind>;
 
 #[derive(SystemSet, Hash, Debug, Clone, Copy, PartialEq, Eq)]
-struct BoardUpdateSystems;
+pub struct BoardUpdateSystems;
 
 #[derive(SystemSet, Hash, Debug, Clone, Copy, PartialEq, Eq)]
 struct RemoveSkipUpdateSystems;
@@ -106,10 +156,14 @@ struct AddSkipUpdateSystems;
 #[derive(Component)]
 pub struct SkipUpdate;
 
+/// Root of a board and the displays around it, so they can be hidden or despawned together.
+#[derive(Component)]
+pub struct BoardGroup;
+
 #[derive(Message)]
 pub struct HoldPieceChanged {
     board: Entity,
-    new_piece_kind: TetrominoKind,
+    new_piece_kind: Option<TetrominoKind>,
 }
 
 #[derive(Message)]
@@ -118,11 +172,73 @@ pub struct TetrominoQueueChanged {
     new_queue: TetrominoQueue,
 }
 
+#[derive(Message)]
+pub struct TetrominoMoved {
+    board: Entity,
+    kind: MoveKind,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MoveKind {
+    Shift,
+    Rotate,
+    /// Every wall kick was blocked.
+    RotateFailed,
+    HardDrop,
+    /// Dropped to the floor without locking, by a sonic, firm or instant soft drop.
+    SonicDrop,
+}
+
 #[derive(Message)]
 pub struct PlaceTetromino {
     board: Entity,
 }
 
+#[derive(Message)]
+pub struct TetrominoPlaced {
+    board: Entity,
+    spin: Spin,
+    kind: TetrominoKind,
+    rotation: TetrominoRotation,
+    pos: IVec2,
+}
+
+#[derive(Message)]
+pub struct LinesCleared {
+    board: Entity,
+    /// Cleared rows, from the bottom up.
+    rows: Vec<u32>,
+    count: u32,
+    /// 0 for a clear made by placing a piece, counting up for each chained clear after it.
+    chain: u32,
+    spin: Spin,
+    perfect_clear: bool,
+}
+
+/// Written when the line clear delay ends and the tiles above the cleared rows drop down.
+#[derive(Message)]
+pub struct LinesCollapsed {
+    board: Entity,
+    /// Chain of the clear that emptied the rows, so a clear after the collapse continues it.
+    chain: u32,
+}
+
+#[derive(Message)]
+pub struct GameOver {
+    board: Entity,
+    reason: GameOverReason,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GameOverReason {
+    ToppedOut,
+    Completed,
+}
+
+/// Added to a board when its game is over. The board stops updating from then on.
+#[derive(Component)]
+pub struct GameEnded;
+
 #[derive(Message)]
 pub struct SpawnNextTetromino {
     board: Entity,
@@ -135,51 +251,87 @@ pub struct SpawnTetromino {
 }
 
 #[derive(Component)]
+#[require(InputBuffer, FumenReplay)]
 pub struct Board {
     kind: TetrominoKind,
     pos: Vec2,
     rotation: TetrominoRotation,
 
     movement: Vec2,
-    stationary_lock_delay: i32,
+    last_kick: Option<usize>,
+    /// Ticks left before the piece locks, counting down while it rests on the stack.
     lock_delay: i32,
-    auto_shift_delay: i32,
+    /// Lock delay resets used since the piece reached its lowest row.
+    lock_resets: u32,
+    /// Lowest row the piece has reached, so falling further can reset the lock delay.
+    lowest_row: i32,
+    /// Whether the piece shifted or rotated since the lock delay was last checked.
+    moved: bool,
+
+    auto_shift_direction: i32,
+    auto_shift_charge: Duration,
+    auto_repeat_time: Duration,
+    das_cut_delay: Duration,
 
     queue: TetrominoQueue,
     random_bag: ShuffleBag<TetrominoKind>,
+    piece_sequence: Option<PieceSequence>,
 
     hold_piece: Option<TetrominoKind>,
-    can_hold: bool,
+    /// Whether hold was used since the last piece was placed.
+    held: bool,
+    holds_used: u32,
+
+    /// Pieces placed so far, used to tell placed pieces apart.
+    placed_pieces: u32,
 }
 
 impl Board {
-    fn new<T: Rng>(mut rng: T) -> Self {
+    fn new<T: Rng>(mut rng: T, piece_sequence: Option<PieceSequence>) -> Self {
         let shuffle_bag = ShuffleBag::try_new(
             TetrominoKind::iter().collect::<Vec<TetrominoKind>>(),
             &mut rng,
         )
         .expect("Failed to create shuffle bag");
-        let mut kinds: Vec<TetrominoKind> = TetrominoKind::iter().collect();
-        kinds.shuffle(&mut rng);
-        let mut queue = TetrominoQueue::from(kinds);
-        queue[0] = get_tetromino_start_piece(&mut rng);
+        let queue = if piece_sequence.is_some() {
+            TetrominoQueue::new()
+        } else {
+            let mut kinds: Vec<TetrominoKind> = TetrominoKind::iter().collect();
+            kinds.shuffle(&mut rng);
+            let mut queue = TetrominoQueue::from(kinds);
+            queue[0] = get_tetromino_start_piece(&mut rng);
+            queue
+        };
 
-        Self {
+        let mut board = Self {
             kind: TetrominoKind::I,
             pos: Default::default(),
             movement: Default::default(),
             rotation: Default::default(),
+            last_kick: None,
 
-            stationary_lock_delay: Default::default(),
             lock_delay: Default::default(),
-            auto_shift_delay: Default::default(),
+            lock_resets: 0,
+            lowest_row: 0,
+            moved: false,
+
+            auto_shift_direction: 0,
+            auto_shift_charge: Duration::ZERO,
+            auto_repeat_time: Duration::ZERO,
+            das_cut_delay: Duration::ZERO,
 
             queue,
             random_bag: shuffle_bag,
+            piece_sequence,
 
             hold_piece: None,
-            can_hold: true,
-        }
+            held: false,
+            holds_used: 0,
+
+            placed_pieces: 0,
+        };
+        board.fill_queue(&mut rng, TetrominoKind::COUNT);
+        board
     }
 
     pub fn can_place(
@@ -193,9 +345,7 @@ impl Board {
         let shape = get_tetromino_shape(self.kind, new_rotation);
         for offset in shape.iter() {
             let pos = new_pos + offset;
-            if pos.x < 0
-                || pos.x >= tilemap.size.x as i32
-                || pos.y < 0
+            if !tilemap.is_in_buffer_bounds(pos)
                 || tilemap.is_tile(self_entity, pos.as_vec2(), placed_tiles)
             {
                 return false;
@@ -205,6 +355,23 @@ impl Board {
         true
     }
 
+    fn get_spin(
+        &self,
+        self_entity: Entity,
+        tilemap: &Tilemap,
+        placed_tiles: Query<&Tile, With<PlacedTile>>,
+    ) -> Spin {
+        if self.kind != TetrominoKind::T {
+            return Spin::None;
+        }
+
+        get_t_spin(self.rotation, self.last_kick, |offset| {
+            let pos = self.get_snapped_pos() + offset;
+            !tilemap.is_in_buffer_bounds(pos)
+                || tilemap.is_tile(self_entity, pos.as_vec2(), placed_tiles)
+        })
+    }
+
     fn get_hard_drop_pos(
         &self,
         self_entity: Entity,
@@ -223,9 +390,111 @@ impl Board {
         result
     }
 
+    /// Moves the piece to where a hard drop would put it without locking it. Returns whether the
+    /// piece moved.
+    fn sonic_drop(
+        &mut self,
+        self_entity: Entity,
+        tilemap: &Tilemap,
+        placed_tiles: Query<&Tile, With<PlacedTile>>,
+    ) -> bool {
+        let start_snapped_pos = self.get_snapped_pos();
+        self.pos.y = self.get_hard_drop_pos(self_entity, tilemap, placed_tiles).y as f32;
+        // Like any other move, dropping after a rotation means the piece wasn't spun into place
+        if self.get_snapped_pos() == start_snapped_pos {
+            return false;
+        }
+        self.last_kick = None;
+        true
+    }
+
+    fn fill_queue<T: Rng>(&mut self, mut rng: T, length: usize) {
+        while self.queue.len() < length {
+            let picked_tetromino = match self.piece_sequence.as_mut().and_then(Iterator::next) {
+                Some(kind) => kind,
+                None => *self.random_bag.pick(&mut rng),
+            };
+            self.queue.push_back(picked_tetromino);
+        }
+    }
+
+    fn can_hold(&self, hold_mode: HoldMode) -> bool {
+        match hold_mode {
+            HoldMode::Disabled => false,
+            HoldMode::OncePerPiece => !self.held,
+            HoldMode::Unlimited => true,
+            HoldMode::Limited(limit) => !self.held && self.holds_used < limit,
+        }
+    }
+
     fn get_snapped_pos(&self) -> IVec2 {
         snap_vec2(self.pos)
     }
+
+    /// Rotates by `direction` steps, trying each wall kick in order.
+    fn rotate(
+        &mut self,
+        self_entity: Entity,
+        tilemap: &Tilemap,
+        placed_tiles: Query<&Tile, With<PlacedTile>>,
+        direction: TetrominoRotation,
+    ) -> bool {
+        let new_rotation = (self.rotation + direction).rem_euclid(4);
+        let offsets = get_tetromino_wall_kicks(self.rotation, new_rotation, self.kind);
+        for (index, offset) in offsets.iter().enumerate() {
+            let new_pos = self.get_snapped_pos() + offset;
+            if self.can_place(self_entity, tilemap, placed_tiles, new_pos, new_rotation) {
+                self.pos += offset.as_vec2();
+                self.rotation = new_rotation;
+                self.last_kick = Some(index);
+                return true;
+            }
+        }
+        false
+    }
+
+    fn set_auto_shift_direction(&mut self, direction: i32) {
+        if direction != self.auto_shift_direction {
+            self.auto_shift_direction = direction;
+            self.auto_shift_charge = Duration::ZERO;
+            self.auto_repeat_time = Duration::ZERO;
+            self.das_cut_delay = Duration::ZERO;
+        }
+    }
+}
+
+fn get_shift_direction(action_state: &ActionState<Action>) -> i32 {
+    if action_state.pressed(&Action::ShiftLeft) {
+        -1
+    } else if action_state.pressed(&Action::ShiftRight) {
+        1
+    } else {
+        0
+    }
+}
+
+fn get_rotation_direction(action_state: &ActionState<Action>) -> Option<TetrominoRotation> {
+    if action_state.just_pressed(&Action::RotateRight) {
+        Some(1)
+    } else if action_state.just_pressed(&Action::RotateLeft) {
+        Some(-1)
+    } else {
+        None
+    }
+}
+
+/// Centres the piece, rounding left, with its lowest minos just above the top of the board. The
+/// position is a little below the row so gravity brings the piece into view straight away.
+fn get_spawn_pos(kind: TetrominoKind, board_size: UVec2) -> Vec2 {
+    let shape = get_tetromino_shape(kind, 0);
+    let min = shape.iter().copied().reduce(IVec2::min).unwrap_or_default();
+    let max = shape.iter().copied().reduce(IVec2::max).unwrap_or_default();
+    let width = max.x - min.x + 1;
+    let left = (board_size.x as i32 - width).div_euclid(2);
+    vec2(
+        (left - min.x) as f32,
+        (board_size.y as i32 - min.y) as f32 - 0.4,
+    )
 }
 
 fn snap_vec2(value: Vec2) -> IVec2 {
@@ -241,28 +510,45 @@ pub fn spawn_board<T: Rng>(
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<ColorMaterial>>,
     mut rng: T,
-    mut spawn_next_messages: MessageWriter<SpawnNextTetromino>,
-) {
+    piece_sequence: Option<PieceSequence>,
+    mode: GameMode,
+    board_config: BoardConfig,
+    spawn_next_messages: &mut MessageWriter<SpawnNextTetromino>,
+) -> Entity {
     let board_backround_size = (size * tile_size).as_vec2();
-    let scale = Vec3::splat(4.0);
-    let tilemap = Tilemap { size, tile_size };
-    let board_config = BoardConfig::default();
-    let board = Board::new(&mut rng);
+    let tilemap = Tilemap {
+        size,
+        tile_size,
+        buffer_height: board_config.buffer_height,
+        visible_buffer_height: VISIBLE_BUFFER_HEIGHT.min(board_config.buffer_height),
+    };
+    let board = Board::new(&mut rng, piece_sequence);
 
     let hold_display_size = uvec2(4, 4);
     let hold_background_size = (hold_display_size * tile_size).as_vec2();
 
     let queue_display_length = board_config.queue_display_length;
-    let queue_display_size = uvec2(4, queue_display_length * 4);
+    let queue_layout = board_config.queue_layout;
+    let queue_display_size = get_queue_display_size(queue_display_length, queue_layout);
     let queue_background_size = (queue_display_size * tile_size).as_vec2();
 
+    let group = commands
+        .spawn((
+            Name::new("BoardGroup"),
+            BoardGroup,
+            Transform::default(),
+            Visibility::default(),
+        ))
+        .id();
+
     let entity = commands
         .spawn((
             Name::new("Board"),
+            ChildOf(group),
             Mesh2d(meshes.add(Rectangle::from_size(board_backround_size))),
             MeshMaterial2d(materials.add(Color::BLACK)),
-            Transform::from_scale(scale),
             get_board_input_map(),
+            get_editor_input_map(),
         ))
         .id();
 
@@ -270,82 +556,115 @@ pub fn spawn_board<T: Rng>(
 
     commands
         .entity(entity)
-        .insert((board, board_config, tilemap));
+        .insert((board, board_config, tilemap, mode, BoardStats::default()));
 
-    // Hold display
+    // Displays are positioned and scaled by `apply_layout`
     commands.spawn((
         Tilemap {
             size: hold_display_size,
             tile_size,
+            ..default()
         },
         HoldDisplay { board: entity },
+        ChildOf(group),
         Mesh2d(meshes.add(Rectangle::from_size(hold_background_size))),
         MeshMaterial2d(materials.add(Color::BLACK)),
-        Transform::from_xyz(-8.0 * 4.0 * 8.0, 8.0 * 4.0 * 8.0, 0.0).with_scale(scale),
     ));
-
-    // Queue Display
     commands.spawn((
         Tilemap {
             size: queue_display_size,
             tile_size,
+            ..default()
         },
         QueueDisplay {
             board: entity,
             length: queue_display_length,
+            layout: queue_layout,
         },
+        ChildOf(group),
         Mesh2d(meshes.add(Rectangle::from_size(queue_background_size))),
         MeshMaterial2d(materials.add(Color::BLACK)),
-        Transform::from_xyz(8.0 * 4.0 * 8.0, 2.0 * 4.0 * 8.0, 0.0).with_scale(scale),
     ));
+    commands.spawn((TimerHud { board: entity }, ChildOf(group)));
+    commands.spawn((ScoreHud { board: entity }, ChildOf(group)));
+
+    entity
 }
 
 fn move_lines_down(
-    mut boards: Query<(Entity, &Tilemap), (With<Board>, Without<SkipUpdate>)>,
-    mut tile_queries: ParamSet<(
-        Query<(Entity, &Tile), With<PlacedTile>>,
-        Query<(Entity, &mut Tile), With<PlacedTile>>,
-    )>,
+    mut commands: Commands,
+    boards: Query<(Entity, &ClearedRows, &BoardConfig), (With<Board>, Without<SkipUpdate>)>,
+    mut placed_tiles: Query<(Entity, &mut Tile, &PlacedTile)>,
+    mut collapsed_messages: MessageWriter<LinesCollapsed>,
 ) {
-    for (board_entity, tilemap) in boards.iter_mut() {
-        let mut num_cleared_lines = 0;
-        for y in 0..tilemap.size.y as i32 {
-            let mut tiles_in_line: Vec<Entity> = vec![];
-
-            for x in 0..tilemap.size.x as i32 {
-                let tile_entities =
-                    tilemap.get_tiles(board_entity, ivec2(x, y).as_vec2(), tile_queries.p0());
-                for tile_entity in tile_entities {
-                    tiles_in_line.push(tile_entity);
-                }
+    for (board_entity, cleared_rows, board_config) in boards.iter() {
+        let tiles: Vec<(Entity, IVec2, PlacedTile)> = placed_tiles
+            .iter()
+            .filter(|(_, tile, _)| tile.tilemap == board_entity)
+            .map(|(entity, tile, placed_tile)| (entity, tile.pos.as_ivec2(), *placed_tile))
+            .collect();
+        let positions: Vec<IVec2> = tiles.iter().map(|(_, pos, _)| *pos).collect();
+
+        let fallen = match board_config.line_clear_gravity {
+            LineClearGravity::Naive => positions
+                .iter()
+                .map(|pos| {
+                    let rows_below = cleared_rows
+                        .rows
+                        .iter()
+                        .filter(|row| (**row as i32) < pos.y)
+                        .count();
+                    *pos - ivec2(0, rows_below as i32)
+                })
+                .collect(),
+            LineClearGravity::Sticky => {
+                get_fallen_positions(&positions, |a, b| tiles[a].2.is_connected(&tiles[b].2))
             }
+            LineClearGravity::Cascade => get_fallen_positions(&positions, |_, _| false),
+        };
 
-            if num_cleared_lines > 0 {
-                for tile_entity in tiles_in_line.iter() {
-                    if let Ok((_, mut tile)) = tile_queries.p1().get_mut(*tile_entity) {
-                        tile.pos -= ivec2(0, num_cleared_lines).as_vec2();
-                    }
-                }
-            }
-            if tiles_in_line.is_empty() {
-                num_cleared_lines += 1;
+        for ((tile_entity, pos, _), fallen_pos) in tiles.iter().zip(fallen) {
+            if *pos != fallen_pos
+                && let Ok((_, mut tile, _)) = placed_tiles.get_mut(*tile_entity)
+            {
+                tile.pos = fallen_pos.as_vec2();
             }
         }
+
+        commands.entity(board_entity).remove::<ClearedRows>();
+        collapsed_messages.write(LinesCollapsed {
+            board: board_entity,
+            chain: cleared_rows.chain,
+        });
     }
 }
 
 fn apply_hold(
-    mut boards: Query<(Entity, &mut Board, &ActionState<Action>), Without<SkipUpdate>>,
+    mut boards: Query<
+        (
+            Entity,
+            &mut Board,
+            &mut InputBuffer,
+            &ActionState<Action>,
+            &BoardConfig,
+        ),
+        Without<SkipUpdate>,
+    >,
     mut hold_messages: MessageWriter<HoldPieceChanged>,
     mut spawn_next_messages: MessageWriter<SpawnNextTetromino>,
     mut spawn_messages: MessageWriter<SpawnTetromino>,
 ) {
-    for (board_entity, mut board, action_state) in boards.iter_mut() {
-        if action_state.just_pressed(&Action::Hold) && board.can_hold {
-            board.can_hold = false;
+    for (board_entity, mut board, mut input_buffer, action_state, board_config) in boards.iter_mut()
+    {
+        let buffered_hold = std::mem::take(&mut input_buffer.hold);
+        if (action_state.just_pressed(&Action::Hold) || buffered_hold)
+            && board.can_hold(board_config.hold_mode)
+        {
+            board.held = true;
+            board.holds_used += 1;
             hold_messages.write(HoldPieceChanged {
                 board: board_entity,
-                new_piece_kind: board.kind,
+                new_piece_kind: Some(board.kind),
             });
 
             let old_hold_piece = board.hold_piece;
@@ -360,7 +679,6 @@ fn apply_hold(
                 spawn_next_messages.write(SpawnNextTetromino {
                     board: board_entity,
                 });
-                board.can_hold = false;
             }
         }
     }
@@ -368,7 +686,7 @@ fn apply_hold(
 
 fn apply_gravity(mut boards: Query<&mut Board, Without<SkipUpdate>>) {
     for mut board in boards.iter_mut() {
-        board.movement.y -= 0.05;
+        board.movement.y -= GRAVITY;
     }
 }
 
@@ -382,35 +700,102 @@ fn apply_shift(mut boards: Query<(&ActionState<Action>, &mut Board), Without<Ski
     }
 }
 
+/// DAS charges while a shift is held, then moves one cell per ARR step. A rotation pauses the
+/// charge for the DAS cut delay.
 fn apply_auto_shift(
-    mut boards: Query<(&ActionState<Action>, &mut Board, &BoardConfig), Without<SkipUpdate>>,
+    mut boards: Query<
+        (&ActionState<Action>, &mut Board, &BoardConfig, &Tilemap),
+        Without<SkipUpdate>,
+    >,
+    time: Res<Time>,
 ) {
-    for (action_state, mut board, board_config) in boards.iter_mut() {
-        let shift = if action_state.pressed(&Action::ShiftLeft) {
-            -1
-        } else if action_state.pressed(&Action::ShiftRight) {
-            1
-        } else {
-            0
-        };
-
+    for (action_state, mut board, board_config, tilemap) in boards.iter_mut() {
+        let shift = get_shift_direction(action_state);
+        board.set_auto_shift_direction(shift);
         if shift == 0 {
-            board.auto_shift_delay = board_config.auto_shift_delay;
             board.pos.x = board.get_snapped_pos().x as f32;
-        } else if board.auto_shift_delay > 0 {
-            board.auto_shift_delay -= 1;
+            continue;
+        }
+
+        if !board.das_cut_delay.is_zero() {
+            board.das_cut_delay = board.das_cut_delay.saturating_sub(time.delta());
+            continue;
+        }
+
+        let auto_shift_delay = board_config.auto_shift_delay();
+        let was_charged = board.auto_shift_charge >= auto_shift_delay;
+        board.auto_shift_charge = (board.auto_shift_charge + time.delta()).min(auto_shift_delay);
+        if board.auto_shift_charge < auto_shift_delay {
+            continue;
+        }
+
+        let auto_repeat_rate = board_config.auto_repeat_rate();
+        if auto_repeat_rate.is_zero() {
+            board.movement.x += (shift * tilemap.size.x as i32) as f32;
+        } else if !was_charged {
+            board.movement.x += shift as f32;
         } else {
-            board.movement.x += board_config.auto_shift_speed * shift as f32;
+            board.auto_repeat_time += time.delta();
+            while board.auto_repeat_time >= auto_repeat_rate {
+                board.auto_repeat_time -= auto_repeat_rate;
+                board.movement.x += shift as f32;
+            }
         }
     }
 }
 
 fn apply_soft_drop(
-    mut boards: Query<(&ActionState<Action>, &mut Board, &BoardConfig), Without<SkipUpdate>>,
+    mut boards: Query<
+        (
+            Entity,
+            &ActionState<Action>,
+            &mut Board,
+            &BoardConfig,
+            &Tilemap,
+        ),
+        Without<SkipUpdate>,
+    >,
+    placed_tiles: Query<&Tile, With<PlacedTile>>,
+    mut moved_messages: MessageWriter<TetrominoMoved>,
 ) {
-    for (action_state, mut board, board_config) in boards.iter_mut() {
-        if action_state.pressed(&Action::SoftDrop) {
-            board.movement.y -= board_config.soft_drop_speed;
+    for (board_entity, action_state, mut board, board_config, tilemap) in boards.iter_mut() {
+        if !action_state.pressed(&Action::SoftDrop) {
+            continue;
+        }
+
+        match board_config.soft_drop_factor {
+            // Gravity is applied separately, so only the extra speed is added here
+            SoftDropFactor::Factor(factor) => board.movement.y -= GRAVITY * (factor - 1.0),
+            SoftDropFactor::Instant => {
+                if board.sonic_drop(board_entity, tilemap, placed_tiles) {
+                    moved_messages.write(TetrominoMoved {
+                        board: board_entity,
+                        kind: MoveKind::SonicDrop,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Moves the piece to where a hard drop would put it, leaving locking to the lock delay.
+fn apply_sonic_drop(
+    mut boards: Query<(Entity, &ActionState<Action>, &mut Board, &Tilemap), Without<SkipUpdate>>,
+    placed_tiles: Query<&Tile, With<PlacedTile>>,
+    mut moved_messages: MessageWriter<TetrominoMoved>,
+) {
+    for (board_entity, action_state, mut board, tilemap) in boards.iter_mut() {
+        if !action_state.pressed(&Action::SonicDrop)
+            && !action_state.just_pressed(&Action::FirmDrop)
+        {
+            continue;
+        }
+
+        if board.sonic_drop(board_entity, tilemap, placed_tiles) {
+            moved_messages.write(TetrominoMoved {
+                board: board_entity,
+                kind: MoveKind::SonicDrop,
+            });
         }
     }
 }
@@ -419,12 +804,17 @@ fn apply_hard_drop(
     mut boards: Query<(Entity, &ActionState<Action>, &mut Board, &Tilemap), Without<SkipUpdate>>,
     placed_tiles: Query<&Tile, With<PlacedTile>>,
     mut place_messages: MessageWriter<PlaceTetromino>,
+    mut moved_messages: MessageWriter<TetrominoMoved>,
 ) {
     for (board_entity, action_state, mut board, tilemap) in boards.iter_mut() {
         if action_state.just_pressed(&Action::HardDrop) {
             board.pos = board
                 .get_hard_drop_pos(board_entity, tilemap, placed_tiles)
                 .as_vec2();
+            moved_messages.write(TetrominoMoved {
+                board: board_entity,
+                kind: MoveKind::HardDrop,
+            });
             place_messages.write(PlaceTetromino {
                 board: board_entity,
             });
@@ -444,27 +834,28 @@ pub fn apply_rotation(
         Without<SkipUpdate>,
     >,
     placed_tiles: Query<&Tile, With<PlacedTile>>,
+    mut moved_messages: MessageWriter<TetrominoMoved>,
 ) {
     for (board_entity, action_state, mut board, board_config, tilemap) in boards.iter_mut() {
-        let new_rotation = if action_state.just_pressed(&Action::RotateRight) {
-            board.rotation + 1
-        } else if action_state.just_pressed(&Action::RotateLeft) {
-            board.rotation - 1
-        } else {
+        let Some(direction) = get_rotation_direction(action_state) else {
             continue;
         };
 
-        board.stationary_lock_delay = board_config.stationary_lock_delay;
-
-        let offsets = get_tetromino_wall_kicks(board.rotation, new_rotation, board.kind);
-        for offset in offsets.iter() {
-            let new_pos = board.get_snapped_pos() + offset;
-            if board.can_place(board_entity, tilemap, placed_tiles, new_pos, new_rotation) {
-                board.pos += offset.as_vec2();
-                board.rotation = new_rotation;
-                return;
+        if board.rotate(board_entity, tilemap, placed_tiles, direction) {
+            board.moved = true;
+            if board.auto_shift_charge >= board_config.auto_shift_delay() {
+                board.das_cut_delay = board_config.das_cut_delay();
             }
+            moved_messages.write(TetrominoMoved {
+                board: board_entity,
+                kind: MoveKind::Rotate,
+            });
+            continue;
         }
+        moved_messages.write(TetrominoMoved {
+            board: board_entity,
+            kind: MoveKind::RotateFailed,
+        });
         bevy::log::warn_once!("All wall kicks failed!");
     }
 }
@@ -472,6 +863,7 @@ pub fn apply_rotation(
 fn apply_movement(
     mut boards: Query<(Entity, &mut Board, &Tilemap), Without<SkipUpdate>>,
     placed_tiles: Query<&Tile, With<PlacedTile>>,
+    mut moved_messages: MessageWriter<TetrominoMoved>,
 ) {
     fn get_range(value: i32) -> Vec<i32> {
         if value.is_positive() {
@@ -483,6 +875,7 @@ fn apply_movement(
 
     for (board_entity, mut board, tilemap) in boards.iter_mut() {
         let start = board.pos;
+        let start_snapped_pos = board.get_snapped_pos();
         let end = board.pos + board.movement;
 
         for (axis, dir) in [ivec2(1, 0), ivec2(0, 1)].iter().enumerate() {
@@ -506,6 +899,17 @@ fn apply_movement(
         }
 
         board.movement = vec2(0.0, 0.0);
+        let snapped_pos = board.get_snapped_pos();
+        if snapped_pos != start_snapped_pos {
+            board.last_kick = None;
+        }
+        if snapped_pos.x != start_snapped_pos.x {
+            board.moved = true;
+            moved_messages.write(TetrominoMoved {
+                board: board_entity,
+                kind: MoveKind::Shift,
+            });
+        }
     }
 }
 
@@ -531,41 +935,47 @@ fn apply_collisions(
 }
 
 fn apply_placement(
-    mut boards: Query<
-        (
-            Entity,
-            &mut Board,
-            &BoardConfig,
-            &Tilemap,
-            &ActionState<Action>,
-        ),
-        Without<SkipUpdate>,
-    >,
+    mut boards: Query<(Entity, &mut Board, &BoardConfig, &Tilemap), Without<SkipUpdate>>,
     placed_tiles: Query<&Tile, With<PlacedTile>>,
     mut place_messages: MessageWriter<PlaceTetromino>,
 ) {
-    for (board_entity, mut board, board_config, tilemap, action_state) in boards.iter_mut() {
-        let pos_below = board.get_snapped_pos() - ivec2(0, 1);
+    for (board_entity, mut board, board_config, tilemap) in boards.iter_mut() {
+        let snapped_pos = board.get_snapped_pos();
+        let moved = std::mem::take(&mut board.moved);
+
+        if snapped_pos.y < board.lowest_row {
+            board.lowest_row = snapped_pos.y;
+            if board_config.lock_mode != LockMode::Classic {
+                board.lock_delay = board_config.lock_delay;
+                board.lock_resets = 0;
+            }
+        }
+
+        // Only moves made after the lock delay started can reset it
+        if moved && board.lock_delay < board_config.lock_delay {
+            match board_config.lock_mode {
+                LockMode::Extended if board.lock_resets < board_config.lock_reset_limit => {
+                    board.lock_delay = board_config.lock_delay;
+                    board.lock_resets += 1;
+                }
+                LockMode::Infinite => board.lock_delay = board_config.lock_delay,
+                _ => {}
+            }
+        }
+
         if board.can_place(
             board_entity,
             tilemap,
             placed_tiles,
-            pos_below,
+            snapped_pos - ivec2(0, 1),
             board.rotation,
         ) || board.pos.y % 1.0 != 0.0
         {
-            board.stationary_lock_delay = board_config.stationary_lock_delay;
             continue; // Piece can still move down
         }
 
         board.lock_delay -= 1;
-        if action_state.pressed(&Action::ShiftLeft) || action_state.pressed(&Action::ShiftRight) {
-            board.stationary_lock_delay = board_config.stationary_lock_delay;
-        } else {
-            board.stationary_lock_delay -= 1;
-        }
-
-        if board.lock_delay < 0 || board.stationary_lock_delay < 0 {
+        if board.lock_delay < 0 {
             place_messages.write(PlaceTetromino {
                 board: board_entity,
             });
@@ -574,7 +984,7 @@ fn apply_placement(
 }
 
 fn place_tetrominos(
-    boards: Query<(Entity, &Board, &Tilemap), Without<SkipUpdate>>,
+    mut boards: Query<(Entity, &mut Board, &Tilemap), Without<SkipUpdate>>,
     mut place_messages: MessageReader<PlaceTetromino>,
     mut commands: Commands,
     placed_tiles: Query<&Tile, With<PlacedTile>>,
@@ -584,10 +994,12 @@ fn place_tetrominos(
         (With<GhostTile>, Without<TetrominoTile>, Without<PlacedTile>),
     >,
     tile_images: Res<TileImages>,
+    garbage_tile_image: Res<GarbageTileImage>,
+    mut placed_messages: MessageWriter<TetrominoPlaced>,
     mut spawn_next_messages: MessageWriter<SpawnNextTetromino>,
 ) {
     for message in place_messages.read() {
-        let Ok((board_entity, board, tilemap)) = boards.get(message.board) else {
+        let Ok((board_entity, mut board, tilemap)) = boards.get_mut(message.board) else {
             bevy::log::error_once!("Failed to get board when spawning next tetromino!");
             break;
         };
@@ -598,20 +1010,30 @@ fn place_tetrominos(
             board.get_snapped_pos(),
             board.rotation,
         ) {
+            placed_messages.write(TetrominoPlaced {
+                board: board_entity,
+                spin: board.get_spin(board_entity, tilemap, placed_tiles),
+                kind: board.kind,
+                rotation: board.rotation,
+                pos: board.get_snapped_pos(),
+            });
             for offset in get_tetromino_shape(board.kind, board.rotation) {
                 let pos = board.get_snapped_pos() + offset;
-                commands.spawn((
-                    Name::new("PlacedTile"),
-                    Tile {
-                        pos: pos.as_vec2(),
-                        tilemap: board_entity,
+                spawn_placed_tile(
+                    &mut commands,
+                    board_entity,
+                    pos,
+                    PlacedTile {
+                        kind: PlacedTileKind::Tetromino(board.kind),
+                        piece: Some(board.placed_pieces),
                     },
-                    PlacedTile,
-                    ChildOf(board_entity),
-                    Sprite::from_image(tile_images.0[&board.kind].clone()),
-                ));
+                    &tile_images,
+                    &garbage_tile_image,
+                );
             }
+            board.placed_pieces += 1;
         }
+        board.held = false;
         clear_tetromino_tiles(&mut commands, board_entity, tetromino_tiles);
         clear_ghost_tiles(&mut commands, board_entity, ghost_tiles);
         spawn_next_messages.write(SpawnNextTetromino {
@@ -621,7 +1043,7 @@ fn place_tetrominos(
 }
 
 fn spawn_next_tetrominos(
-    mut boards: Query<(Entity, &mut Board), Without<SkipUpdate>>,
+    mut boards: Query<(Entity, &mut Board, &BoardConfig), Without<SkipUpdate>>,
     mut spawn_next_messages: MessageReader<SpawnNextTetromino>,
     mut spawn_messages: MessageWriter<SpawnTetromino>,
     mut queue_messages: MessageWriter<TetrominoQueueChanged>,
@@ -630,7 +1052,7 @@ fn spawn_next_tetrominos(
     let mut rng = &mut random_source.0;
 
     for message in spawn_next_messages.read() {
-        let Ok((board_entity, mut board)) = boards.get_mut(message.board) else {
+        let Ok((board_entity, mut board, board_config)) = boards.get_mut(message.board) else {
             bevy::log::error_once!("Failed to get board when spawning next tetromino!");
             break;
         };
@@ -639,13 +1061,29 @@ fn spawn_next_tetrominos(
             error_once!("Attempted to pop from empty piece queue!");
             return;
         };
-        let picked_tetromino = *board.random_bag.pick(&mut rng);
-        board.queue.push_back(picked_tetromino);
+        board.fill_queue(&mut rng, board_config.queue_length());
         spawn_messages.write(SpawnTetromino {
             board: board_entity,
             kind,
         });
-        board.can_hold = true;
+        queue_messages.write(TetrominoQueueChanged {
+            board: board_entity,
+            new_queue: board.queue.clone(),
+        });
+    }
+}
+
+/// Tops the queue up when the preview count is raised, so there are always pieces to show.
+fn fill_queues(
+    mut boards: Query<(Entity, &mut Board, &BoardConfig), Without<SkipUpdate>>,
+    mut queue_messages: MessageWriter<TetrominoQueueChanged>,
+    mut random_source: ResMut<RandomSource>,
+) {
+    for (board_entity, mut board, board_config) in boards.iter_mut() {
+        if board.queue.len() >= board_config.queue_length() {
+            continue;
+        }
+        board.fill_queue(&mut random_source.0, board_config.queue_length());
         queue_messages.write(TetrominoQueueChanged {
             board: board_entity,
             new_queue: board.queue.clone(),
@@ -660,6 +1098,8 @@ fn spawn_tetrominos(
     placed_tiles: Query<&Tile, With<PlacedTile>>,
     tile_images: Res<TileImages>,
     tile_outline_images: Res<TileOutlineImages>,
+    ghost_tile_images: Res<GhostTileImages>,
+    mut game_over_messages: MessageWriter<GameOver>,
 ) {
     for message in messages.read() {
         let Ok((board_entity, mut board, tilemap, board_config)) = boards.get_mut(message.board)
@@ -669,9 +1109,17 @@ fn spawn_tetrominos(
         };
 
         board.kind = message.kind;
-        board.pos = vec2(4.0, tilemap.size.y as f32 - 0.4);
+        board.pos = get_spawn_pos(board.kind, tilemap.size);
         board.rotation = 0;
+        board.last_kick = None;
         board.lock_delay = board_config.lock_delay;
+        board.lock_resets = 0;
+        board.lowest_row = board.get_snapped_pos().y;
+        board.moved = false;
+        if !board_config.das_carry {
+            board.auto_shift_charge = Duration::ZERO;
+            board.auto_repeat_time = Duration::ZERO;
+        }
 
         if board.can_place(
             board_entity,
@@ -687,15 +1135,164 @@ fn spawn_tetrominos(
                 &tile_images,
                 &tile_outline_images,
             );
-            spawn_ghost_tiles(&mut commands, &board, board_entity, &tile_outline_images);
+            spawn_ghost_tiles(&mut commands, &board, board_entity, &ghost_tile_images);
         } else {
-            bevy::log::error_once!("Attempted to spawn tetromino at invalid position");
+            commands.entity(board_entity).insert(GameEnded);
+            game_over_messages.write(GameOver {
+                board: board_entity,
+                reason: GameOverReason::ToppedOut,
+            });
         }
     }
 }
 
+/// Applies a rotation buffered during a delay, once the piece it's meant for is in play.
+fn apply_initial_rotation(
+    mut boards: Query<(Entity, &mut Board, &mut InputBuffer, &Tilemap), Without<SkipUpdate>>,
+    placed_tiles: Query<&Tile, With<PlacedTile>>,
+    mut moved_messages: MessageWriter<TetrominoMoved>,
+) {
+    for (board_entity, mut board, mut input_buffer, tilemap) in boards.iter_mut() {
+        let Some(direction) = input_buffer.rotation.take() else {
+            continue;
+        };
+        let kind = if board.rotate(board_entity, tilemap, placed_tiles, direction) {
+            MoveKind::Rotate
+        } else {
+            MoveKind::RotateFailed
+        };
+        moved_messages.write(TetrominoMoved {
+            board: board_entity,
+            kind,
+        });
+    }
+}
+
 fn remove_skip_update(mut commands: Commands, boards: Query<Entity, With<SkipUpdate>>) {
     for board_entity in boards {
         commands.entity(board_entity).remove::<SkipUpdate>();
     }
 }
+
+fn apply_game_ended_skip_update(mut commands: Commands, boards: Query<Entity, With<GameEnded>>) {
+    for board_entity in boards {
+        commands.entity(board_entity).insert(SkipUpdate);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg32;
+
+    use super::*;
+
+    fn spawn_test_board(world: &mut World, kind: TetrominoKind) -> Entity {
+        let mut board = Board::new(Pcg32::seed_from_u64(0), None);
+        board.kind = kind;
+        board.pos = vec2(4.0, 10.0);
+        let tilemap = Tilemap {
+            size: uvec2(10, 20),
+            tile_size: uvec2(8, 8),
+            buffer_height: 20,
+            visible_buffer_height: 2,
+        };
+        world.spawn((board, tilemap)).id()
+    }
+
+    #[test]
+    fn rotates_left_from_spawn() {
+        for kind in TetrominoKind::iter() {
+            let mut world = World::new();
+            let entity = spawn_test_board(&mut world, kind);
+
+            let rotation = world
+                .run_system_once(
+                    move |mut boards: Query<(&mut Board, &Tilemap)>,
+                          placed_tiles: Query<&Tile, With<PlacedTile>>| {
+                        let (mut board, tilemap) = boards.get_mut(entity).unwrap();
+                        assert!(board.rotate(entity, tilemap, placed_tiles, -1));
+                        assert!(board.rotate(entity, tilemap, placed_tiles, -1));
+                        board.rotation
+                    },
+                )
+                .unwrap();
+            assert_eq!(rotation, 2, "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn rotates_every_board_in_the_same_tick() {
+        let mut world = World::new();
+        world.init_resource::<Messages<TetrominoMoved>>();
+        let boards = [
+            spawn_test_board(&mut world, TetrominoKind::T),
+            spawn_test_board(&mut world, TetrominoKind::L),
+        ];
+        for entity in boards {
+            let mut action_state = ActionState::<Action>::default();
+            action_state.press(&Action::RotateRight);
+            world
+                .entity_mut(entity)
+                .insert((action_state, BoardConfig::default()));
+        }
+
+        world.run_system_once(apply_rotation).unwrap();
+        for entity in boards {
+            assert_eq!(world.get::<Board>(entity).unwrap().rotation, 1);
+        }
+    }
+
+    #[test]
+    fn sonic_drops_reset_the_last_kick() {
+        let mut world = World::new();
+        world.init_resource::<Messages<TetrominoMoved>>();
+        let entity = spawn_test_board(&mut world, TetrominoKind::T);
+        let mut action_state = ActionState::<Action>::default();
+        action_state.press(&Action::SonicDrop);
+        world.entity_mut(entity).insert(action_state);
+        world.get_mut::<Board>(entity).unwrap().last_kick = Some(0);
+
+        world.run_system_once(apply_sonic_drop).unwrap();
+        let board = world.get::<Board>(entity).unwrap();
+        assert_eq!(board.pos.y, 0.0);
+        assert_eq!(board.last_kick, None);
+        assert_eq!(world.resource::<Messages<TetrominoMoved>>().len(), 1);
+
+        // Holding the drop on the floor doesn't move the piece, so a rotation there still counts
+        world.get_mut::<Board>(entity).unwrap().last_kick = Some(0);
+        world.run_system_once(apply_sonic_drop).unwrap();
+        assert_eq!(world.get::<Board>(entity).unwrap().last_kick, Some(0));
+        assert_eq!(world.resource::<Messages<TetrominoMoved>>().len(), 1);
+    }
+
+    #[test]
+    fn instant_soft_drops_reset_the_last_kick() {
+        let mut world = World::new();
+        world.init_resource::<Messages<TetrominoMoved>>();
+        let entity = spawn_test_board(&mut world, TetrominoKind::T);
+        let mut action_state = ActionState::<Action>::default();
+        action_state.press(&Action::SoftDrop);
+        let board_config = BoardConfig {
+            soft_drop_factor: SoftDropFactor::Instant,
+            ..default()
+        };
+        world
+            .entity_mut(entity)
+            .insert((action_state, board_config));
+        world.get_mut::<Board>(entity).unwrap().last_kick = Some(0);
+
+        world.run_system_once(apply_soft_drop).unwrap();
+        let board = world.get::<Board>(entity).unwrap();
+        assert_eq!(board.pos.y, 0.0);
+        assert_eq!(board.last_kick, None);
+        assert_eq!(world.resource::<Messages<TetrominoMoved>>().len(), 1);
+
+        // Holding the drop on the floor doesn't move the piece, so a rotation there still counts
+        world.get_mut::<Board>(entity).unwrap().last_kick = Some(0);
+        world.run_system_once(apply_soft_drop).unwrap();
+        assert_eq!(world.get::<Board>(entity).unwrap().last_kick, Some(0));
+        assert_eq!(world.resource::<Messages<TetrominoMoved>>().len(), 1);
+    }
+}
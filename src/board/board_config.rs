@@ -1,38 +1,533 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use strum::EnumCount;
+
+use crate::board::{
+    BoardUpdateSystems,
+    piece_sequence::{PieceSequence, PieceSequenceError},
+    tetromino_data::TetrominoKind,
+};
+
+const MAX_QUEUE_DISPLAY_LENGTH: u32 = 14;
+/// Narrow enough for 4 wide drills, the I piece needs at least 4 columns.
+const MIN_BOARD_SIZE: UVec2 = uvec2(4, 4);
+const MAX_BOARD_SIZE: UVec2 = uvec2(40, 100);
+/// Pieces spawn in the two rows above the board.
+const MIN_BUFFER_HEIGHT: u32 = 2;
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct BoardConfigPlugin;
+
+impl Plugin for BoardConfigPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(BoardSettings::load()).add_systems(
+            FixedUpdate,
+            reload_board_settings.before(BoardUpdateSystems),
+        );
+    }
+}
 
-#[derive(Component)]
+/// Handling times are in milliseconds, lock and line clear times are in ticks.
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
 pub struct BoardConfig {
-    pub auto_shift_delay: i32,
-    pub auto_shift_speed: f32,
+    pub board_width: u32,
+    pub board_height: u32,
+    /// Hidden rows above the board that pieces can still move and lock in.
+    pub buffer_height: u32,
 
-    pub stationary_lock_delay: i32,
+    /// How long a shift is held before auto shift starts.
+    pub auto_shift_delay_ms: u32,
+    /// Time between auto shift steps. 0 moves the piece straight to the wall.
+    pub auto_repeat_rate_ms: u32,
+    /// Pause in auto shift after a rotation, so a charged shift doesn't drag the rotated piece.
+    pub das_cut_delay_ms: u32,
+    /// Whether auto shift charge is kept when the next piece spawns.
+    pub das_carry: bool,
+
+    /// How long a piece rests on the stack before it locks.
     pub lock_delay: i32,
+    pub lock_mode: LockMode,
+    /// Lock delay resets allowed per row with extended placement.
+    pub lock_reset_limit: u32,
 
-    pub soft_drop_speed: f32,
+    pub soft_drop_factor: SoftDropFactor,
+    pub hold_mode: HoldMode,
 
     pub line_clear_fade_time: i32,
     pub line_clear_delay: i32,
     pub line_clear_horizontal_delay: i32,
+    pub line_clear_gravity: LineClearGravity,
 
     pub queue_display_length: u32,
+    pub queue_layout: QueueLayout,
+    /// The queue stays hidden until this many pieces have been placed.
+    pub queue_hidden_pieces: u32,
+    /// Fixed piece order such as `"TIOLJSZ..."`, for drilling openers like TKI or DT cannon.
+    pub piece_sequence: Option<String>,
 }
 
 impl Default for BoardConfig {
     fn default() -> Self {
         Self {
-            auto_shift_delay: 10,
-            auto_shift_speed: 0.25,
+            board_width: 10,
+            board_height: 20,
+            buffer_height: 20,
+
+            auto_shift_delay_ms: 167,
+            auto_repeat_rate_ms: 33,
+            das_cut_delay_ms: 0,
+            das_carry: true,
 
-            stationary_lock_delay: 40,
-            lock_delay: 200,
+            lock_delay: 30,
+            lock_mode: LockMode::Extended,
+            lock_reset_limit: 15,
 
-            soft_drop_speed: 0.25,
+            soft_drop_factor: SoftDropFactor::Factor(6.0),
+            hold_mode: HoldMode::OncePerPiece,
 
             line_clear_fade_time: 5,
             line_clear_delay: 10,
             line_clear_horizontal_delay: 2,
+            line_clear_gravity: LineClearGravity::Naive,
 
             queue_display_length: 4,
+            queue_layout: QueueLayout::Vertical,
+            queue_hidden_pieces: 0,
+            piece_sequence: None,
+        }
+    }
+}
+
+/// Soft drop speed as a multiple of gravity.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum SoftDropFactor {
+    Factor(f32),
+    /// Drops the piece to the floor without locking it.
+    Instant,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum QueueLayout {
+    Vertical,
+    /// Pieces side by side, for wide screens.
+    Horizontal,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HoldMode {
+    Disabled,
+    OncePerPiece,
+    /// Pieces can be swapped with the hold any number of times.
+    Unlimited,
+    /// Once per piece, up to this many times a game.
+    Limited(u32),
+}
+
+/// What resets the lock delay. Reaching a new lowest row resets it in every mode except classic.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LockMode {
+    /// Moving or rotating resets the lock delay, up to `lock_reset_limit` times. Reaching a new
+    /// lowest row gives the resets back.
+    Extended,
+    /// Moving or rotating always resets the lock delay.
+    Infinite,
+    /// Only reaching a new lowest row resets the lock delay.
+    StepReset,
+    /// The lock delay never resets, so each piece gets it once.
+    Classic,
+}
+
+/// How tiles above cleared lines fall once the line clear delay ends. With sticky and cascade
+/// gravity, lines filled by falling tiles clear again as a chain.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LineClearGravity {
+    /// Rows drop by the number of cleared lines below them, leaving holes where they are.
+    Naive,
+    /// Connected tiles of the same piece fall together until they land.
+    Sticky,
+    /// Every tile falls on its own until it lands.
+    Cascade,
+}
+
+#[derive(Debug)]
+pub enum BoardConfigError {
+    Read(io::Error),
+    Parse(ron::error::SpannedError),
+    Negative(&'static str, i32),
+    SoftDropTooSlow(f32),
+    QueueDisplayTooLong(u32),
+    BoardSizeOutOfRange(UVec2),
+    BufferTooShort(u32),
+    InvalidPieceSequence(PieceSequenceError),
+}
+
+impl fmt::Display for BoardConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BoardConfigError::Read(error) => write!(f, "failed to read settings: {error}"),
+            BoardConfigError::Parse(error) => write!(f, "invalid settings: {error}"),
+            BoardConfigError::Negative(name, value) => {
+                write!(f, "`{name}` is {value}, but can't be negative")
+            }
+            BoardConfigError::SoftDropTooSlow(factor) => {
+                write!(f, "`soft_drop_factor` is {factor}, but must be at least 1")
+            }
+            BoardConfigError::QueueDisplayTooLong(length) => write!(
+                f,
+                "`queue_display_length` is {length}, but at most {MAX_QUEUE_DISPLAY_LENGTH} pieces can be shown"
+            ),
+            BoardConfigError::BufferTooShort(height) => write!(
+                f,
+                "`buffer_height` is {height}, but pieces need at least {MIN_BUFFER_HEIGHT} rows to spawn in"
+            ),
+            BoardConfigError::InvalidPieceSequence(error) => {
+                write!(f, "`piece_sequence` is invalid: {error}")
+            }
+            BoardConfigError::BoardSizeOutOfRange(size) => write!(
+                f,
+                "the board is {}x{}, but must be from {}x{} to {}x{}",
+                size.x,
+                size.y,
+                MIN_BOARD_SIZE.x,
+                MIN_BOARD_SIZE.y,
+                MAX_BOARD_SIZE.x,
+                MAX_BOARD_SIZE.y
+            ),
         }
     }
 }
+
+impl std::error::Error for BoardConfigError {}
+
+impl BoardConfig {
+    pub fn board_size(&self) -> UVec2 {
+        uvec2(self.board_width, self.board_height)
+    }
+
+    /// Pieces kept in the queue, enough to fill the queue display.
+    pub fn queue_length(&self) -> usize {
+        (self.queue_display_length as usize).max(TetrominoKind::COUNT)
+    }
+
+    pub fn auto_shift_delay(&self) -> Duration {
+        Duration::from_millis(self.auto_shift_delay_ms as u64)
+    }
+
+    pub fn auto_repeat_rate(&self) -> Duration {
+        Duration::from_millis(self.auto_repeat_rate_ms as u64)
+    }
+
+    pub fn das_cut_delay(&self) -> Duration {
+        Duration::from_millis(self.das_cut_delay_ms as u64)
+    }
+
+    pub fn piece_sequence(&self) -> Option<PieceSequence> {
+        self.piece_sequence
+            .as_ref()
+            .and_then(|sequence| sequence.parse().ok())
+    }
+
+    pub fn validate(&self) -> Result<(), BoardConfigError> {
+        for (name, value) in [
+            ("lock_delay", self.lock_delay),
+            ("line_clear_fade_time", self.line_clear_fade_time),
+            ("line_clear_delay", self.line_clear_delay),
+            (
+                "line_clear_horizontal_delay",
+                self.line_clear_horizontal_delay,
+            ),
+        ] {
+            if value < 0 {
+                return Err(BoardConfigError::Negative(name, value));
+            }
+        }
+
+        if let SoftDropFactor::Factor(factor) = self.soft_drop_factor
+            && !(factor >= 1.0 && factor.is_finite())
+        {
+            return Err(BoardConfigError::SoftDropTooSlow(factor));
+        }
+
+        if self.queue_display_length > MAX_QUEUE_DISPLAY_LENGTH {
+            return Err(BoardConfigError::QueueDisplayTooLong(
+                self.queue_display_length,
+            ));
+        }
+
+        let size = self.board_size();
+        if size.cmplt(MIN_BOARD_SIZE).any() || size.cmpgt(MAX_BOARD_SIZE).any() {
+            return Err(BoardConfigError::BoardSizeOutOfRange(size));
+        }
+
+        if self.buffer_height < MIN_BUFFER_HEIGHT {
+            return Err(BoardConfigError::BufferTooShort(self.buffer_height));
+        }
+
+        if let Some(sequence) = &self.piece_sequence {
+            sequence
+                .parse::<PieceSequence>()
+                .map_err(BoardConfigError::InvalidPieceSequence)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, BoardConfigError> {
+        let data = fs::read_to_string(path).map_err(BoardConfigError::Read)?;
+        Self::from_ron(&data)
+    }
+
+    fn from_ron(data: &str) -> Result<Self, BoardConfigError> {
+        let config: BoardConfig = ron::from_str(data).map_err(BoardConfigError::Parse)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// A reloaded config for a live board, keeping the size, buffer height and piece sequence the
+    /// board was spawned with.
+    fn reloaded(&self, config: &BoardConfig) -> BoardConfig {
+        BoardConfig {
+            board_width: self.board_width,
+            board_height: self.board_height,
+            buffer_height: self.buffer_height,
+            piece_sequence: self.piece_sequence.clone(),
+            ..config.clone()
+        }
+    }
+}
+
+/// Board config loaded from `settings.ron` in the user's config directory. The file is polled for
+/// changes, and a valid edit is applied to every board straight away.
+#[derive(Resource)]
+pub struct BoardSettings {
+    pub config: BoardConfig,
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    reload_timer: Timer,
+}
+
+impl BoardSettings {
+    fn load() -> Self {
+        let path = dirs::config_dir().map(|dir| dir.join("tetrominoes").join("settings.ron"));
+        let mut settings = Self {
+            config: BoardConfig::default(),
+            path,
+            modified: None,
+            reload_timer: Timer::new(RELOAD_INTERVAL, TimerMode::Repeating),
+        };
+        let Some(path) = &settings.path else {
+            return settings;
+        };
+
+        if !path.exists() {
+            write_default_settings(path);
+        }
+        settings.modified = get_modified(path);
+        match BoardConfig::from_file(path) {
+            Ok(config) => settings.config = config,
+            Err(error) => error!("{}: {error}, using default settings", path.display()),
+        }
+        settings
+    }
+}
+
+fn write_default_settings(path: &Path) {
+    let result = ron::ser::to_string_pretty(&BoardConfig::default(), Default::default())
+        .map_err(io::Error::other)
+        .and_then(|data| {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, data)
+        });
+    if let Err(error) = result {
+        error!(
+            "Failed to write default settings to {}: {error}",
+            path.display()
+        );
+    }
+}
+
+fn get_modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+// ========== Systems ==========
+
+/// The board size, buffer height and piece sequence keep the values the board was spawned with,
+/// everything else applies immediately.
+fn reload_board_settings(
+    mut settings: ResMut<BoardSettings>,
+    mut board_configs: Query<&mut BoardConfig>,
+    time: Res<Time>,
+) {
+    if !settings.reload_timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let Some(path) = settings.path.clone() else {
+        return;
+    };
+
+    let modified = get_modified(&path);
+    if modified.is_none() || modified == settings.modified {
+        return;
+    }
+    settings.modified = modified;
+
+    match BoardConfig::from_file(&path) {
+        Ok(config) => {
+            info!("Reloaded settings from {}", path.display());
+            for mut board_config in board_configs.iter_mut() {
+                *board_config = board_config.reloaded(&config);
+            }
+            settings.config = config;
+        }
+        Err(error) => error!("{}: {error}, keeping previous settings", path.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_error(config: BoardConfig) -> BoardConfigError {
+        config.validate().unwrap_err()
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        assert!(BoardConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn missing_files_fail_to_read() {
+        let path = std::env::temp_dir().join("tetrominoes_missing_settings.ron");
+        assert!(matches!(
+            BoardConfig::from_file(&path),
+            Err(BoardConfigError::Read(_))
+        ));
+    }
+
+    #[test]
+    fn unknown_fields_fail_to_parse() {
+        assert!(matches!(
+            BoardConfig::from_ron("(gravity: 20)"),
+            Err(BoardConfigError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn negative_times_are_rejected() {
+        let config = BoardConfig {
+            line_clear_delay: -1,
+            ..default()
+        };
+        assert!(matches!(
+            get_error(config),
+            BoardConfigError::Negative("line_clear_delay", -1)
+        ));
+    }
+
+    #[test]
+    fn slow_soft_drops_are_rejected() {
+        let config = BoardConfig {
+            soft_drop_factor: SoftDropFactor::Factor(0.5),
+            ..default()
+        };
+        assert!(matches!(
+            get_error(config),
+            BoardConfigError::SoftDropTooSlow(0.5)
+        ));
+    }
+
+    #[test]
+    fn long_queue_displays_are_rejected() {
+        let config = BoardConfig {
+            queue_display_length: MAX_QUEUE_DISPLAY_LENGTH + 1,
+            ..default()
+        };
+        assert!(matches!(
+            get_error(config),
+            BoardConfigError::QueueDisplayTooLong(15)
+        ));
+    }
+
+    #[test]
+    fn board_sizes_out_of_range_are_rejected() {
+        let config = BoardConfig {
+            board_width: 3,
+            ..default()
+        };
+        assert!(matches!(
+            get_error(config),
+            BoardConfigError::BoardSizeOutOfRange(UVec2 { x: 3, y: 20 })
+        ));
+    }
+
+    #[test]
+    fn short_buffers_are_rejected() {
+        let config = BoardConfig {
+            buffer_height: 1,
+            ..default()
+        };
+        assert!(matches!(
+            get_error(config),
+            BoardConfigError::BufferTooShort(1)
+        ));
+    }
+
+    #[test]
+    fn invalid_piece_sequences_are_rejected() {
+        let config = BoardConfig {
+            piece_sequence: Some("TX".to_string()),
+            ..default()
+        };
+        assert!(matches!(
+            get_error(config),
+            BoardConfigError::InvalidPieceSequence(PieceSequenceError::InvalidPiece('X'))
+        ));
+    }
+
+    #[test]
+    fn config_round_trips_through_ron() {
+        let config = BoardConfig {
+            auto_repeat_rate_ms: 0,
+            soft_drop_factor: SoftDropFactor::Instant,
+            hold_mode: HoldMode::Limited(3),
+            line_clear_gravity: LineClearGravity::Cascade,
+            piece_sequence: Some("TIOLJSZ...".to_string()),
+            ..default()
+        };
+        let data = ron::ser::to_string_pretty(&config, Default::default()).unwrap();
+        assert_eq!(BoardConfig::from_ron(&data).unwrap(), config);
+    }
+
+    #[test]
+    fn reloads_keep_the_spawn_size_and_sequence() {
+        let spawned = BoardConfig {
+            board_width: 4,
+            buffer_height: 4,
+            piece_sequence: Some("I".to_string()),
+            ..default()
+        };
+        let config = BoardConfig {
+            board_width: 12,
+            board_height: 24,
+            auto_shift_delay_ms: 100,
+            ..default()
+        };
+
+        let reloaded = spawned.reloaded(&config);
+        assert_eq!(reloaded.board_size(), uvec2(4, 20));
+        assert_eq!(reloaded.buffer_height, 4);
+        assert_eq!(reloaded.piece_sequence.as_deref(), Some("I"));
+        assert_eq!(reloaded.auto_shift_delay_ms, 100);
+    }
+}
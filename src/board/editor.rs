@@ -0,0 +1,284 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use leafwing_input_manager::prelude::ActionState;
+use strum::{EnumCount, IntoEnumIterator};
+
+use crate::{
+    board::{
+        AddSkipUpdateSystems, Board, BoardUpdateSystems, HoldPieceChanged, SkipUpdate,
+        SpawnNextTetromino, TetrominoQueue, TetrominoQueueChanged,
+        fumen::FumenReplay,
+        ghost_tile::{GhostTile, clear_ghost_tiles},
+        placed_tile::{PlacedTile, PlacedTileKind, spawn_placed_tile},
+        tetromino_data::TetrominoKind,
+        tetromino_tile::{TetrominoTile, clear_tetromino_tiles},
+        tile_assets::{GarbageTileImage, TileImages, get_tile_image},
+    },
+    input::EditorAction,
+    rng::RandomSource,
+    tiles::{Tile, Tilemap},
+};
+
+pub struct BoardEditorPlugin;
+
+impl Plugin for BoardEditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (
+                (
+                    toggle_board_editors,
+                    apply_editor_kind_selection,
+                    apply_editor_hold,
+                    apply_editor_queue,
+                    apply_editor_painting,
+                )
+                    .chain()
+                    .before(BoardUpdateSystems),
+                apply_editor_skip_update.in_set(AddSkipUpdateSystems),
+            ),
+        );
+    }
+}
+
+/// Present on a board while it is being edited. The board is paused for the whole edit, and the
+/// hold piece and queue set here replace the board's own when editing ends.
+#[derive(Component)]
+pub struct BoardEditor {
+    kind: TetrominoKind,
+    pub queue: TetrominoQueue,
+    pub hold_piece: Option<TetrominoKind>,
+    /// Tile above the board's top left corner showing the kind being painted with.
+    preview: Entity,
+}
+
+fn spawn_kind_preview(
+    commands: &mut Commands,
+    board_entity: Entity,
+    tilemap: &Tilemap,
+    image: Handle<Image>,
+) -> Entity {
+    let tile_size = tilemap.tile_size.as_vec2();
+    let pos = vec2(
+        0.5 - tilemap.size.x as f32 / 2.0,
+        tilemap.size.y as f32 / 2.0 + 0.5,
+    ) * tile_size;
+    commands
+        .spawn((
+            Name::new("EditorKindPreview"),
+            ChildOf(board_entity),
+            Sprite::from_image(image),
+            Transform::from_translation(pos.extend(1.0)),
+        ))
+        .id()
+}
+
+fn toggle_board_editors(
+    mut commands: Commands,
+    mut boards: Query<(
+        Entity,
+        &mut Board,
+        &mut FumenReplay,
+        &Tilemap,
+        &ActionState<EditorAction>,
+        Option<&BoardEditor>,
+    )>,
+    tetromino_tiles: Query<(Entity, &Tile), (With<TetrominoTile>, Without<PlacedTile>)>,
+    ghost_tiles: Query<
+        (Entity, &Tile),
+        (With<GhostTile>, Without<TetrominoTile>, Without<PlacedTile>),
+    >,
+    tile_images: Res<TileImages>,
+    mut random_source: ResMut<RandomSource>,
+    mut spawn_next_messages: MessageWriter<SpawnNextTetromino>,
+) {
+    for (board_entity, mut board, mut replay, tilemap, action_state, editor) in boards.iter_mut() {
+        if !action_state.just_pressed(&EditorAction::Toggle) {
+            continue;
+        }
+
+        if let Some(editor) = editor {
+            board.queue = editor.queue.clone();
+            board.fill_queue(&mut random_source.0, TetrominoKind::COUNT);
+            board.hold_piece = editor.hold_piece;
+            board.held = false;
+
+            commands.entity(editor.preview).despawn();
+            commands
+                .entity(board_entity)
+                .remove::<(BoardEditor, SkipUpdate)>();
+            spawn_next_messages.write(SpawnNextTetromino {
+                board: board_entity,
+            });
+        } else {
+            let mut queue = board.queue.clone();
+            queue.push_front(board.kind);
+            // The edited board is a new setup, so its replay starts over
+            replay.0.clear();
+
+            let image = get_tile_image(&tile_images.0, board.kind);
+            let preview = spawn_kind_preview(&mut commands, board_entity, tilemap, image);
+            commands.entity(board_entity).insert((
+                BoardEditor {
+                    kind: board.kind,
+                    queue,
+                    hold_piece: board.hold_piece,
+                    preview,
+                },
+                SkipUpdate,
+            ));
+            clear_tetromino_tiles(&mut commands, board_entity, tetromino_tiles);
+            clear_ghost_tiles(&mut commands, board_entity, ghost_tiles);
+        }
+    }
+}
+
+fn apply_editor_kind_selection(
+    mut boards: Query<(&mut BoardEditor, &ActionState<EditorAction>)>,
+    mut previews: Query<&mut Sprite>,
+    tile_images: Res<TileImages>,
+) {
+    for (mut editor, action_state) in boards.iter_mut() {
+        let step = if action_state.just_pressed(&EditorAction::NextKind) {
+            1
+        } else if action_state.just_pressed(&EditorAction::PreviousKind) {
+            TetrominoKind::COUNT - 1
+        } else {
+            continue;
+        };
+
+        let index = TetrominoKind::iter()
+            .position(|kind| kind == editor.kind)
+            .unwrap_or_default();
+        editor.kind = TetrominoKind::iter()
+            .nth((index + step) % TetrominoKind::COUNT)
+            .expect("Kind index is always in range");
+        if let Ok(mut sprite) = previews.get_mut(editor.preview) {
+            sprite.image = get_tile_image(&tile_images.0, editor.kind);
+        }
+    }
+}
+
+fn apply_editor_hold(
+    mut boards: Query<(Entity, &mut BoardEditor, &ActionState<EditorAction>)>,
+    mut hold_messages: MessageWriter<HoldPieceChanged>,
+) {
+    for (board_entity, mut editor, action_state) in boards.iter_mut() {
+        if !action_state.just_pressed(&EditorAction::ToggleHold) {
+            continue;
+        }
+
+        editor.hold_piece = if editor.hold_piece == Some(editor.kind) {
+            None
+        } else {
+            Some(editor.kind)
+        };
+        hold_messages.write(HoldPieceChanged {
+            board: board_entity,
+            new_piece_kind: editor.hold_piece,
+        });
+    }
+}
+
+fn apply_editor_queue(
+    mut boards: Query<(Entity, &mut BoardEditor, &ActionState<EditorAction>)>,
+    mut queue_messages: MessageWriter<TetrominoQueueChanged>,
+) {
+    for (board_entity, mut editor, action_state) in boards.iter_mut() {
+        if action_state.just_pressed(&EditorAction::PushQueue) {
+            let kind = editor.kind;
+            editor.queue.push_back(kind);
+        } else if action_state.just_pressed(&EditorAction::PopQueue) {
+            editor.queue.pop_back();
+        } else if action_state.just_pressed(&EditorAction::ClearQueue) {
+            editor.queue.clear();
+        } else {
+            continue;
+        }
+
+        queue_messages.write(TetrominoQueueChanged {
+            board: board_entity,
+            new_queue: editor.queue.clone(),
+        });
+    }
+}
+
+fn apply_editor_painting(
+    mut commands: Commands,
+    boards: Query<(
+        Entity,
+        &BoardEditor,
+        &Tilemap,
+        &GlobalTransform,
+        &ActionState<EditorAction>,
+    )>,
+    placed_tiles: Query<(Entity, &Tile), With<PlacedTile>>,
+    placed_tile_kinds: Query<&PlacedTile>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera: Single<(&Camera, &GlobalTransform)>,
+    tile_images: Res<TileImages>,
+    garbage_tile_image: Res<GarbageTileImage>,
+) {
+    let (camera, camera_transform) = *camera;
+    let Some(cursor_pos) = window
+        .cursor_position()
+        .and_then(|pos| camera.viewport_to_world_2d(camera_transform, pos).ok())
+    else {
+        return;
+    };
+
+    for (board_entity, editor, tilemap, board_transform, action_state) in boards.iter() {
+        let paint = action_state.pressed(&EditorAction::Paint);
+        let erase = action_state.pressed(&EditorAction::Erase);
+        if !paint && !erase {
+            continue;
+        }
+
+        let local_pos = board_transform
+            .affine()
+            .inverse()
+            .transform_point3(cursor_pos.extend(0.0))
+            .truncate();
+        let tile_pos = (local_pos / tilemap.tile_size.as_vec2() + tilemap.size.as_vec2() / 2.0
+            - vec2(0.5, 0.5))
+        .round()
+        .as_ivec2();
+        if !tilemap.is_in_bounds(tile_pos) {
+            continue;
+        }
+
+        let tile_entities = tilemap.get_tiles(board_entity, tile_pos.as_vec2(), placed_tiles);
+        if paint
+            && !tile_entities.is_empty()
+            && tile_entities.iter().all(|entity| {
+                placed_tile_kinds.get(*entity).is_ok_and(|placed_tile| {
+                    placed_tile.kind == PlacedTileKind::Tetromino(editor.kind)
+                })
+            })
+        {
+            continue; // Already painted with this kind
+        }
+
+        for tile_entity in tile_entities {
+            commands.entity(tile_entity).despawn();
+        }
+        if paint {
+            spawn_placed_tile(
+                &mut commands,
+                board_entity,
+                tile_pos,
+                PlacedTile {
+                    kind: PlacedTileKind::Tetromino(editor.kind),
+                    piece: None,
+                },
+                &tile_images,
+                &garbage_tile_image,
+            );
+        }
+    }
+}
+
+fn apply_editor_skip_update(mut commands: Commands, boards: Query<Entity, With<BoardEditor>>) {
+    for board_entity in boards {
+        commands.entity(board_entity).insert(SkipUpdate);
+    }
+}
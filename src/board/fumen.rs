@@ -0,0 +1,1073 @@
+//! Encoding and decoding of fumen (v115) diagrams, the format the community uses to share boards
+//! and setups. See <https://github.com/knewjade/tetris-fumen> for the reference implementation.
+
+use std::{fmt, fs, path::PathBuf, str::FromStr};
+
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::ActionState;
+use strum::EnumCount;
+
+use crate::{
+    board::{
+        Board, BoardUpdateSystems, HoldPieceChanged, RemoveSkipUpdateSystems, SpawnNextTetromino,
+        TetrominoPlaced, TetrominoQueue,
+        editor::BoardEditor,
+        ghost_tile::{GhostTile, clear_ghost_tiles},
+        line_clear::LineClearSystems,
+        placed_tile::{PlacedTile, PlacedTileKind, spawn_placed_tile},
+        tetromino_data::{TetrominoKind, TetrominoRotation, get_tetromino_shape},
+        tetromino_tile::{TetrominoTile, clear_tetromino_tiles},
+        tile_assets::{GarbageTileImage, TileImages},
+    },
+    input::EditorAction,
+    rng::RandomSource,
+    tiles::{Tile, Tilemap},
+};
+
+pub struct FumenPlugin;
+
+impl Plugin for FumenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (
+                record_fumen_pages
+                    .after(BoardUpdateSystems)
+                    .before(LineClearSystems),
+                ((import_fumens, load_fumens).chain(), export_fumens)
+                    .after(BoardUpdateSystems)
+                    .before(RemoveSkipUpdateSystems),
+            ),
+        )
+        .add_message::<LoadFumen>();
+    }
+}
+
+/// Replaces a board's placed tiles, hold piece and queue with one page of a fumen. The hold piece
+/// and queue are read from the page's quiz comment when it has one.
+#[derive(Message)]
+pub struct LoadFumen {
+    pub board: Entity,
+    pub data: String,
+    pub page: usize,
+}
+
+/// A page for each piece placed on a board, so the game can be exported as a replay. Loading a
+/// fumen or opening the editor starts a new replay.
+#[derive(Component, Default)]
+pub struct FumenReplay(pub Vec<FumenPage>);
+
+pub const FIELD_WIDTH: usize = 10;
+pub const FIELD_TOP: usize = 23;
+const FIELD_BLOCKS: usize = (FIELD_TOP + 1) * FIELD_WIDTH;
+
+const VERSION_PREFIX: &str = "v115@";
+const ENCODE_TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const COMMENT_TABLE: &[u8] = b" !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+const COMMENT_TABLE_SIZE: u32 = COMMENT_TABLE.len() as u32 + 1;
+const MAX_COMMENT_LENGTH: usize = 4095;
+const QUIZ_PREFIX: &str = "#Q=";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FumenError {
+    UnsupportedVersion,
+    InvalidCharacter(char),
+    UnexpectedEnd,
+    InvalidCell(u32),
+    InvalidPiece(u32),
+    InvalidQuiz(String),
+}
+
+impl fmt::Display for FumenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FumenError::UnsupportedVersion => write!(f, "only v115 fumen data is supported"),
+            FumenError::InvalidCharacter(c) => write!(f, "invalid character '{c}' in fumen data"),
+            FumenError::UnexpectedEnd => write!(f, "fumen data ended unexpectedly"),
+            FumenError::InvalidCell(value) => write!(f, "invalid field cell value {value}"),
+            FumenError::InvalidPiece(value) => write!(f, "invalid piece value {value}"),
+            FumenError::InvalidQuiz(comment) => write!(f, "invalid quiz comment \"{comment}\""),
+        }
+    }
+}
+
+impl std::error::Error for FumenError {}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FumenCell {
+    #[default]
+    Empty,
+    Tetromino(TetrominoKind),
+    Garbage,
+}
+
+impl FumenCell {
+    const fn code(self) -> u32 {
+        match self {
+            FumenCell::Empty => 0,
+            FumenCell::Tetromino(TetrominoKind::I) => 1,
+            FumenCell::Tetromino(TetrominoKind::L) => 2,
+            FumenCell::Tetromino(TetrominoKind::O) => 3,
+            FumenCell::Tetromino(TetrominoKind::Z) => 4,
+            FumenCell::Tetromino(TetrominoKind::T) => 5,
+            FumenCell::Tetromino(TetrominoKind::J) => 6,
+            FumenCell::Tetromino(TetrominoKind::S) => 7,
+            FumenCell::Garbage => 8,
+        }
+    }
+
+    const fn from_code(code: u32) -> Option<Self> {
+        Some(match code {
+            0 => FumenCell::Empty,
+            1 => FumenCell::Tetromino(TetrominoKind::I),
+            2 => FumenCell::Tetromino(TetrominoKind::L),
+            3 => FumenCell::Tetromino(TetrominoKind::O),
+            4 => FumenCell::Tetromino(TetrominoKind::Z),
+            5 => FumenCell::Tetromino(TetrominoKind::T),
+            6 => FumenCell::Tetromino(TetrominoKind::J),
+            7 => FumenCell::Tetromino(TetrominoKind::S),
+            8 => FumenCell::Garbage,
+            _ => return None,
+        })
+    }
+}
+
+/// The 10 wide fumen playfield. Rows are counted up from the bottom of the playfield, with the
+/// garbage row below it at `y == -1`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FumenField {
+    cells: [FumenCell; FIELD_BLOCKS],
+}
+
+impl Default for FumenField {
+    fn default() -> Self {
+        Self {
+            cells: [FumenCell::Empty; FIELD_BLOCKS],
+        }
+    }
+}
+
+impl FumenField {
+    pub fn get(&self, pos: IVec2) -> FumenCell {
+        Self::index(pos)
+            .map(|index| self.cells[index])
+            .unwrap_or_default()
+    }
+
+    pub fn set(&mut self, pos: IVec2, cell: FumenCell) {
+        if let Some(index) = Self::index(pos) {
+            self.cells[index] = cell;
+        }
+    }
+
+    /// Cells are stored in the order fumen encodes them: top row first, garbage row last.
+    fn index(pos: IVec2) -> Option<usize> {
+        if pos.x < 0 || pos.x >= FIELD_WIDTH as i32 || pos.y < -1 || pos.y >= FIELD_TOP as i32 {
+            return None;
+        }
+        Some((FIELD_TOP as i32 - 1 - pos.y) as usize * FIELD_WIDTH + pos.x as usize)
+    }
+
+    fn put(&mut self, piece: &FumenPiece) {
+        for offset in get_tetromino_shape(piece.kind, piece.rotation) {
+            self.set(piece.pos + offset, FumenCell::Tetromino(piece.kind));
+        }
+    }
+
+    fn clear_lines(&mut self) {
+        let mut target_y = 0;
+        for y in 0..FIELD_TOP as i32 {
+            let row = self.row(y);
+            if row.iter().all(|cell| *cell != FumenCell::Empty) {
+                continue;
+            }
+            self.set_row(target_y, row);
+            target_y += 1;
+        }
+        for y in target_y..FIELD_TOP as i32 {
+            self.set_row(y, [FumenCell::Empty; FIELD_WIDTH]);
+        }
+    }
+
+    fn rise_garbage(&mut self) {
+        for y in (0..FIELD_TOP as i32).rev() {
+            let row = self.row(y - 1);
+            self.set_row(y, row);
+        }
+        self.set_row(-1, [FumenCell::Empty; FIELD_WIDTH]);
+    }
+
+    fn mirror(&mut self) {
+        for y in 0..FIELD_TOP as i32 {
+            let mut row = self.row(y);
+            row.reverse();
+            self.set_row(y, row);
+        }
+    }
+
+    fn row(&self, y: i32) -> [FumenCell; FIELD_WIDTH] {
+        std::array::from_fn(|x| self.get(ivec2(x as i32, y)))
+    }
+
+    fn set_row(&mut self, y: i32, row: [FumenCell; FIELD_WIDTH]) {
+        for (x, cell) in row.into_iter().enumerate() {
+            self.set(ivec2(x as i32, y), cell);
+        }
+    }
+}
+
+/// A piece on a fumen page, using the same shapes, rotations and positions as the board.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FumenPiece {
+    pub kind: TetrominoKind,
+    pub rotation: TetrominoRotation,
+    pub pos: IVec2,
+}
+
+impl FumenPiece {
+    /// Fumen anchors a few pieces and rotations on a different mino than the board does.
+    fn anchor_offset(&self) -> IVec2 {
+        use TetrominoKind::*;
+        match (self.kind, self.rotation.rem_euclid(4)) {
+            (O, 0) => ivec2(0, -1),
+            (O, 2) => ivec2(1, 0),
+            (O, 3) => ivec2(1, -1),
+            (I, 2) => ivec2(1, 0),
+            (I, 3) => ivec2(0, -1),
+            (S, 0) => ivec2(0, -1),
+            (S, 1) => ivec2(-1, 0),
+            (Z, 0) => ivec2(0, -1),
+            (Z, 3) => ivec2(1, 0),
+            _ => IVec2::ZERO,
+        }
+    }
+}
+
+/// Fumen numbers rotations as reverse, right, spawn, left. The mapping is its own inverse.
+const fn fumen_rotation(rotation: u32) -> u32 {
+    match rotation % 4 {
+        0 => 2,
+        2 => 0,
+        other => other,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FumenPage {
+    pub field: FumenField,
+    pub piece: Option<FumenPiece>,
+    pub comment: String,
+    pub lock: bool,
+    pub rise: bool,
+    pub mirror: bool,
+    pub colorize: bool,
+}
+
+impl Default for FumenPage {
+    fn default() -> Self {
+        Self {
+            field: Default::default(),
+            piece: None,
+            comment: String::new(),
+            lock: true,
+            rise: false,
+            mirror: false,
+            colorize: true,
+        }
+    }
+}
+
+/// The hold piece, current piece and queue carried by a `#Q=[H](C)NEXT` quiz comment.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FumenQuiz {
+    pub hold_piece: Option<TetrominoKind>,
+    pub current: Option<TetrominoKind>,
+    pub queue: TetrominoQueue,
+}
+
+impl FromStr for FumenQuiz {
+    type Err = FumenError;
+
+    fn from_str(comment: &str) -> Result<Self, Self::Err> {
+        let invalid = || FumenError::InvalidQuiz(comment.to_string());
+        let parse_kind = |c: char| TetrominoKind::from_str(&c.to_string()).map_err(|_| invalid());
+
+        let rest = comment.strip_prefix(QUIZ_PREFIX).ok_or_else(invalid)?;
+        let (hold, rest) = rest
+            .strip_prefix('[')
+            .and_then(|rest| rest.split_once(']'))
+            .ok_or_else(invalid)?;
+        let (current, queue) = rest
+            .strip_prefix('(')
+            .and_then(|rest| rest.split_once(')'))
+            .ok_or_else(invalid)?;
+
+        let single_kind = |value: &str| match value.chars().collect::<Vec<_>>()[..] {
+            [] => Ok(None),
+            [c] => parse_kind(c).map(Some),
+            _ => Err(invalid()),
+        };
+
+        Ok(Self {
+            hold_piece: single_kind(hold)?,
+            current: single_kind(current)?,
+            queue: queue
+                .trim()
+                .chars()
+                .map(parse_kind)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl fmt::Display for FumenQuiz {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind_str = |kind: Option<TetrominoKind>| kind.map_or("", <&str>::from);
+        write!(
+            f,
+            "{QUIZ_PREFIX}[{}]({})",
+            kind_str(self.hold_piece),
+            kind_str(self.current)
+        )?;
+        for kind in self.queue.iter() {
+            write!(f, "{}", <&str>::from(*kind))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn decode(data: &str) -> Result<Vec<FumenPage>, FumenError> {
+    let version_index = data
+        .find(&VERSION_PREFIX[1..])
+        .ok_or(FumenError::UnsupportedVersion)?;
+    let mut values = FumenValues::parse(&data[version_index + VERSION_PREFIX.len() - 1..])?;
+
+    let mut pages = vec![];
+    let mut prev_field = FumenField::default();
+    let mut prev_comment = String::new();
+    let mut repeat_count = 0;
+
+    while !values.is_empty() {
+        let mut field = prev_field.clone();
+        if repeat_count > 0 {
+            repeat_count -= 1;
+        } else {
+            let mut index = 0;
+            while index < FIELD_BLOCKS {
+                let run = values.poll(2)?;
+                let diff = run / FIELD_BLOCKS as u32;
+                let length = run as usize % FIELD_BLOCKS + 1;
+                if diff == 8 && length == FIELD_BLOCKS {
+                    repeat_count = values.poll(1)?;
+                }
+                for cell in field.cells.iter_mut().skip(index).take(length) {
+                    let code = (cell.code() + diff)
+                        .checked_sub(8)
+                        .ok_or(FumenError::InvalidCell(diff))?;
+                    *cell = FumenCell::from_code(code).ok_or(FumenError::InvalidCell(code))?;
+                }
+                index += length;
+            }
+        }
+
+        let mut action = values.poll(3)?;
+        let mut take = |count: u32| {
+            let value = action % count;
+            action /= count;
+            value
+        };
+        let piece_code = take(8);
+        let rotation = fumen_rotation(take(4)) as TetrominoRotation;
+        let location = take(FIELD_BLOCKS as u32) as i32;
+        let rise = take(2) == 1;
+        let mirror = take(2) == 1;
+        let colorize = take(2) == 1;
+        let has_comment = take(2) == 1;
+        let lock = take(2) == 0;
+
+        let piece = match FumenCell::from_code(piece_code) {
+            Some(FumenCell::Empty) => None,
+            Some(FumenCell::Tetromino(kind)) => {
+                let mut piece = FumenPiece {
+                    kind,
+                    rotation,
+                    pos: ivec2(
+                        location % FIELD_WIDTH as i32,
+                        FIELD_TOP as i32 - location / FIELD_WIDTH as i32 - 1,
+                    ),
+                };
+                piece.pos += piece.anchor_offset();
+                Some(piece)
+            }
+            _ => return Err(FumenError::InvalidPiece(piece_code)),
+        };
+
+        if has_comment {
+            let length = values.poll(2)? as usize;
+            let mut escaped = String::new();
+            for _ in 0..length.div_ceil(4) {
+                let mut block = values.poll(5)?;
+                for _ in 0..4 {
+                    let index = (block % COMMENT_TABLE_SIZE) as usize;
+                    escaped.push(*COMMENT_TABLE.get(index).unwrap_or(&b' ') as char);
+                    block /= COMMENT_TABLE_SIZE;
+                }
+            }
+            escaped.truncate(length);
+            prev_comment = unescape(&escaped);
+        }
+
+        let page = FumenPage {
+            field: field.clone(),
+            piece,
+            comment: prev_comment.clone(),
+            lock,
+            rise,
+            mirror,
+            colorize,
+        };
+        if lock {
+            if let Some(piece) = piece {
+                field.put(&piece);
+            }
+            field.clear_lines();
+            if rise {
+                field.rise_garbage();
+            }
+            if mirror {
+                field.mirror();
+            }
+        }
+
+        pages.push(page);
+        prev_field = field;
+    }
+
+    Ok(pages)
+}
+
+pub fn encode(pages: &[FumenPage]) -> String {
+    let mut values = FumenValues::default();
+    let mut prev_field = FumenField::default();
+    let mut prev_comment = String::new();
+    let mut last_repeat_index = None;
+
+    for (page_index, page) in pages.iter().enumerate() {
+        let (field_values, changed) = encode_field(&prev_field, &page.field);
+        if changed {
+            values.0.extend(field_values.0);
+            last_repeat_index = None;
+        } else {
+            match last_repeat_index {
+                Some(index) if values.0[index] < ENCODE_TABLE.len() as u32 - 1 => {
+                    values.0[index] += 1;
+                }
+                _ => {
+                    values.0.extend(field_values.0);
+                    values.push(0, 1);
+                    last_repeat_index = Some(values.len() - 1);
+                }
+            }
+        }
+
+        let has_comment = page.comment != prev_comment;
+        let (piece_code, rotation, location) = match page.piece {
+            Some(piece) => {
+                let pos = piece.pos - piece.anchor_offset();
+                (
+                    FumenCell::Tetromino(piece.kind).code(),
+                    fumen_rotation(piece.rotation.rem_euclid(4) as u32),
+                    (FIELD_TOP as i32 - pos.y - 1) * FIELD_WIDTH as i32 + pos.x,
+                )
+            }
+            None => (0, 0, 0),
+        };
+        let mut action = 0;
+        for flag in [
+            !page.lock,
+            has_comment,
+            page.colorize && page_index == 0,
+            page.mirror,
+            page.rise,
+        ] {
+            action = action * 2 + flag as u32;
+        }
+        action = action * FIELD_BLOCKS as u32 + location.clamp(0, FIELD_BLOCKS as i32 - 1) as u32;
+        action = action * 4 + rotation;
+        action = action * 8 + piece_code;
+        values.push(action, 3);
+
+        if has_comment {
+            let escaped = escape(&page.comment);
+            let escaped = &escaped.as_bytes()[..escaped.len().min(MAX_COMMENT_LENGTH)];
+            values.push(escaped.len() as u32, 2);
+            for chunk in escaped.chunks(4) {
+                let mut block = 0;
+                for c in chunk.iter().rev() {
+                    let index = COMMENT_TABLE.iter().position(|t| t == c).unwrap_or(0) as u32;
+                    block = block * COMMENT_TABLE_SIZE + index;
+                }
+                values.push(block, 5);
+            }
+            prev_comment = page.comment.clone();
+        }
+
+        let mut field = page.field.clone();
+        if page.lock {
+            if let Some(piece) = page.piece {
+                field.put(&piece);
+            }
+            field.clear_lines();
+            if page.rise {
+                field.rise_garbage();
+            }
+            if page.mirror {
+                field.mirror();
+            }
+        }
+        prev_field = field;
+    }
+
+    let data = values.to_string();
+    let mut result = String::from(VERSION_PREFIX);
+    let (head, mut tail) = data.split_at(data.len().min(42));
+    result.push_str(head);
+    while !tail.is_empty() {
+        let (chunk, rest) = tail.split_at(tail.len().min(47));
+        result.push('?');
+        result.push_str(chunk);
+        tail = rest;
+    }
+    result
+}
+
+/// Run length encodes the difference between two fields, and reports whether there was one.
+fn encode_field(prev: &FumenField, current: &FumenField) -> (FumenValues, bool) {
+    let mut values = FumenValues::default();
+    let mut diffs = prev
+        .cells
+        .iter()
+        .zip(current.cells.iter())
+        .map(|(prev, current)| current.code() + 8 - prev.code());
+
+    let mut run_diff = diffs.next().unwrap_or(8);
+    let mut run_length = 1;
+    for diff in diffs {
+        if diff == run_diff {
+            run_length += 1;
+        } else {
+            values.push(run_diff * FIELD_BLOCKS as u32 + run_length - 1, 2);
+            run_diff = diff;
+            run_length = 1;
+        }
+    }
+    values.push(run_diff * FIELD_BLOCKS as u32 + run_length - 1, 2);
+    let changed = run_diff != 8 || run_length != FIELD_BLOCKS as u32;
+    (values, changed)
+}
+
+/// Base 64 digits, least significant first.
+#[derive(Default)]
+struct FumenValues(Vec<u32>);
+
+impl FumenValues {
+    fn parse(data: &str) -> Result<Self, FumenError> {
+        data.chars()
+            .filter(|c| *c != '?')
+            .map(|c| {
+                ENCODE_TABLE
+                    .iter()
+                    .position(|e| *e as char == c)
+                    .map(|index| index as u32)
+                    .ok_or(FumenError::InvalidCharacter(c))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|mut digits| {
+                digits.reverse();
+                Self(digits)
+            })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Reads a value from the front. Parsed values are stored reversed so this can pop.
+    fn poll(&mut self, digits: u32) -> Result<u32, FumenError> {
+        let mut value = 0;
+        for digit in 0..digits {
+            let next = self.0.pop().ok_or(FumenError::UnexpectedEnd)?;
+            value += next * (ENCODE_TABLE.len() as u32).pow(digit);
+        }
+        Ok(value)
+    }
+
+    fn push(&mut self, mut value: u32, digits: u32) {
+        for _ in 0..digits {
+            self.0.push(value % ENCODE_TABLE.len() as u32);
+            value /= ENCODE_TABLE.len() as u32;
+        }
+    }
+}
+
+impl fmt::Display for FumenValues {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for value in self.0.iter() {
+            write!(f, "{}", ENCODE_TABLE[*value as usize] as char)?;
+        }
+        Ok(())
+    }
+}
+
+/// Matches JavaScript's `escape`, which fumen applies to comments.
+fn escape(text: &str) -> String {
+    let mut result = String::new();
+    for unit in text.encode_utf16() {
+        match char::from_u32(unit as u32) {
+            Some(c) if c.is_ascii_alphanumeric() || "@*_+-./".contains(c) => result.push(c),
+            _ if unit < 0x100 => result.push_str(&format!("%{unit:02X}")),
+            _ => result.push_str(&format!("%u{unit:04X}")),
+        }
+    }
+    result
+}
+
+/// Matches JavaScript's `unescape`. Malformed escapes are kept as they are.
+fn unescape(text: &str) -> String {
+    let mut units = vec![];
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let hex = |digits: &str| u16::from_str_radix(digits, 16).ok();
+        let (unit, length) = match (rest.get(1..2), rest.get(2..6), rest.get(1..3)) {
+            _ if c != '%' => (None, 0),
+            (Some("u"), Some(digits), _) => (hex(digits), 6),
+            (_, _, Some(digits)) => (hex(digits), 3),
+            _ => (None, 0),
+        };
+        match unit {
+            Some(unit) => {
+                units.push(unit);
+                rest = &rest[length..];
+            }
+            None => {
+                let mut buffer = [0; 2];
+                units.extend_from_slice(c.encode_utf16(&mut buffer));
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    String::from_utf16_lossy(&units)
+}
+
+/// Where fumens are exported to and imported from. Paste a fumen in here to import it.
+fn get_fumen_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("tetrominoes").join("fumen.txt"))
+}
+
+fn get_field(board_entity: Entity, placed_tiles: &Query<(&Tile, &PlacedTile)>) -> FumenField {
+    let mut field = FumenField::default();
+    for (tile, placed_tile) in placed_tiles {
+        if tile.tilemap != board_entity {
+            continue;
+        }
+        let cell = match placed_tile.kind {
+            PlacedTileKind::Tetromino(kind) => FumenCell::Tetromino(kind),
+            PlacedTileKind::Garbage => FumenCell::Garbage,
+        };
+        field.set(tile.pos.as_ivec2(), cell);
+    }
+    field
+}
+
+// ========== Systems ==========
+
+/// Runs before full lines are cleared, so the field still matches the board the piece landed on.
+fn record_fumen_pages(
+    mut boards: Query<(&mut FumenReplay, &Tilemap)>,
+    mut placed_messages: MessageReader<TetrominoPlaced>,
+    placed_tiles: Query<(&Tile, &PlacedTile)>,
+) {
+    for message in placed_messages.read() {
+        let Ok((mut replay, tilemap)) = boards.get_mut(message.board) else {
+            continue;
+        };
+        if tilemap.size.x as usize != FIELD_WIDTH {
+            continue;
+        }
+
+        let piece = FumenPiece {
+            kind: message.kind,
+            rotation: message.rotation,
+            pos: message.pos,
+        };
+        // The piece's own tiles may already be spawned, so they're taken back out
+        let mut field = get_field(message.board, &placed_tiles);
+        for offset in get_tetromino_shape(piece.kind, piece.rotation) {
+            field.set(piece.pos + offset, FumenCell::Empty);
+        }
+        let colorize = replay.0.is_empty();
+        replay.0.push(FumenPage {
+            field,
+            piece: Some(piece),
+            colorize,
+            ..Default::default()
+        });
+    }
+}
+
+/// Loads the first page of the fumen in `fumen.txt`.
+fn import_fumens(
+    boards: Query<(Entity, &ActionState<EditorAction>)>,
+    mut load_messages: MessageWriter<LoadFumen>,
+) {
+    for (board_entity, action_state) in boards.iter() {
+        if !action_state.just_pressed(&EditorAction::ImportFumen) {
+            continue;
+        }
+        let Some(path) = get_fumen_path() else {
+            bevy::log::error!("No data directory to import a fumen from");
+            continue;
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(data) => {
+                load_messages.write(LoadFumen {
+                    board: board_entity,
+                    data: data.trim().to_string(),
+                    page: 0,
+                });
+            }
+            Err(error) => bevy::log::error!("Failed to read {}: {error}", path.display()),
+        }
+    }
+}
+
+fn load_fumens(
+    mut commands: Commands,
+    mut boards: Query<(Entity, &mut Board, &mut FumenReplay, &Tilemap)>,
+    mut load_messages: MessageReader<LoadFumen>,
+    placed_tiles: Query<(Entity, &Tile), With<PlacedTile>>,
+    tetromino_tiles: Query<(Entity, &Tile), (With<TetrominoTile>, Without<PlacedTile>)>,
+    ghost_tiles: Query<
+        (Entity, &Tile),
+        (With<GhostTile>, Without<TetrominoTile>, Without<PlacedTile>),
+    >,
+    tile_images: Res<TileImages>,
+    garbage_tile_image: Res<GarbageTileImage>,
+    mut random_source: ResMut<RandomSource>,
+    mut hold_messages: MessageWriter<HoldPieceChanged>,
+    mut spawn_next_messages: MessageWriter<SpawnNextTetromino>,
+) {
+    for message in load_messages.read() {
+        let Ok((board_entity, mut board, mut replay, tilemap)) = boards.get_mut(message.board)
+        else {
+            bevy::log::error_once!("Failed to get board when loading fumen!");
+            continue;
+        };
+        if tilemap.size.x as usize != FIELD_WIDTH {
+            bevy::log::error!("Fumen can only be loaded into {FIELD_WIDTH} wide boards");
+            continue;
+        }
+
+        let page = decode(&message.data).and_then(|mut pages| {
+            if message.page < pages.len() {
+                Ok(pages.swap_remove(message.page))
+            } else {
+                Err(FumenError::UnexpectedEnd)
+            }
+        });
+        let quiz = page.as_ref().map_err(Clone::clone).and_then(|page| {
+            if page.comment.starts_with(QUIZ_PREFIX) {
+                page.comment.parse::<FumenQuiz>()
+            } else {
+                Ok(FumenQuiz::default())
+            }
+        });
+        let (page, quiz) = match (page, quiz) {
+            (Ok(page), Ok(quiz)) => (page, quiz),
+            (Err(error), _) | (_, Err(error)) => {
+                bevy::log::error!("Failed to load fumen: {error}");
+                continue;
+            }
+        };
+
+        for (tile_entity, tile) in placed_tiles {
+            if tile.tilemap == board_entity {
+                commands.entity(tile_entity).despawn();
+            }
+        }
+        for y in 0..(tilemap.total_height() as i32).min(FIELD_TOP as i32) {
+            for x in 0..FIELD_WIDTH as i32 {
+                let kind = match page.field.get(ivec2(x, y)) {
+                    FumenCell::Empty => continue,
+                    FumenCell::Tetromino(kind) => PlacedTileKind::Tetromino(kind),
+                    FumenCell::Garbage => PlacedTileKind::Garbage,
+                };
+                spawn_placed_tile(
+                    &mut commands,
+                    board_entity,
+                    ivec2(x, y),
+                    PlacedTile { kind, piece: None },
+                    &tile_images,
+                    &garbage_tile_image,
+                );
+            }
+        }
+
+        if quiz.current.is_some() || !quiz.queue.is_empty() {
+            board.queue = quiz.current.into_iter().chain(quiz.queue).collect();
+        } else {
+            let kind = board.kind;
+            board.queue.push_front(kind);
+        }
+        board.fill_queue(&mut random_source.0, TetrominoKind::COUNT);
+        board.hold_piece = quiz.hold_piece;
+        board.held = false;
+        replay.0.clear();
+
+        clear_tetromino_tiles(&mut commands, board_entity, tetromino_tiles);
+        clear_ghost_tiles(&mut commands, board_entity, ghost_tiles);
+        hold_messages.write(HoldPieceChanged {
+            board: board_entity,
+            new_piece_kind: board.hold_piece,
+        });
+        spawn_next_messages.write(SpawnNextTetromino {
+            board: board_entity,
+        });
+    }
+}
+
+/// Writes the board's replay to `fumen.txt`, followed by a page for the board as it is now.
+fn export_fumens(
+    boards: Query<(
+        Entity,
+        &Board,
+        &FumenReplay,
+        &Tilemap,
+        &ActionState<EditorAction>,
+        Option<&BoardEditor>,
+    )>,
+    placed_tiles: Query<(&Tile, &PlacedTile)>,
+) {
+    for (board_entity, board, replay, tilemap, action_state, editor) in boards.iter() {
+        if !action_state.just_pressed(&EditorAction::ExportFumen) {
+            continue;
+        }
+        if tilemap.size.x as usize != FIELD_WIDTH {
+            bevy::log::error!("Fumen can only be exported from {FIELD_WIDTH} wide boards");
+            continue;
+        }
+
+        // An editor holds the current piece at the front of its queue
+        let quiz = match editor {
+            Some(editor) => FumenQuiz {
+                hold_piece: editor.hold_piece,
+                current: editor.queue.front().copied(),
+                queue: editor.queue.iter().skip(1).copied().collect(),
+            },
+            None => FumenQuiz {
+                hold_piece: board.hold_piece,
+                current: Some(board.kind),
+                queue: board.queue.clone(),
+            },
+        };
+
+        let mut pages = replay.0.clone();
+        pages.push(FumenPage {
+            field: get_field(board_entity, &placed_tiles),
+            comment: quiz.to_string(),
+            colorize: pages.is_empty(),
+            ..Default::default()
+        });
+        let data = encode(&pages);
+        bevy::log::info!("Fumen: {data}");
+
+        let Some(path) = get_fumen_path() else {
+            bevy::log::error!("No data directory to export the fumen to");
+            continue;
+        };
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, data));
+        match result {
+            Ok(()) => bevy::log::info!("Exported fumen to {}", path.display()),
+            Err(error) => bevy::log::error!("Failed to write {}: {error}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EMPTY: &str = "v115@vhAAgH";
+    /// Nine garbage tiles on the bottom row, with T held, I current and LJSZO next.
+    const GARBAGE_QUIZ: &str = "v115@bhI8KeAgWaAFLDmClcJSAVztSAVG88A4N88AZintCa?HBAA";
+
+    #[test]
+    fn decodes_empty_field() {
+        let pages = decode(EMPTY).unwrap();
+        assert_eq!(pages, vec![FumenPage::default()]);
+    }
+
+    #[test]
+    fn decodes_field_with_quiz_comment() {
+        let pages = decode(GARBAGE_QUIZ).unwrap();
+        assert_eq!(pages.len(), 1);
+        let page = &pages[0];
+
+        for x in 0..FIELD_WIDTH as i32 {
+            let expected = if x < 9 {
+                FumenCell::Garbage
+            } else {
+                FumenCell::Empty
+            };
+            assert_eq!(page.field.get(ivec2(x, 0)), expected, "x = {x}");
+            assert_eq!(page.field.get(ivec2(x, 1)), FumenCell::Empty);
+            assert_eq!(page.field.get(ivec2(x, -1)), FumenCell::Empty);
+        }
+        assert_eq!(page.piece, None);
+        assert_eq!(page.comment, "#Q=[T](I)LJSZO");
+
+        let quiz: FumenQuiz = page.comment.parse().unwrap();
+        assert_eq!(quiz.hold_piece, Some(TetrominoKind::T));
+        assert_eq!(quiz.current, Some(TetrominoKind::I));
+        assert_eq!(
+            quiz.queue,
+            TetrominoQueue::from([
+                TetrominoKind::L,
+                TetrominoKind::J,
+                TetrominoKind::S,
+                TetrominoKind::Z,
+                TetrominoKind::O,
+            ])
+        );
+        assert_eq!(quiz.to_string(), page.comment);
+    }
+
+    #[test]
+    fn encoding_decoded_data_round_trips() {
+        for data in [EMPTY, GARBAGE_QUIZ] {
+            assert_eq!(encode(&decode(data).unwrap()), data);
+        }
+    }
+
+    #[test]
+    fn decoding_encoded_pages_round_trips() {
+        let piece = FumenPiece {
+            kind: TetrominoKind::T,
+            rotation: 0,
+            pos: ivec2(4, 0),
+        };
+        let first = FumenPage {
+            piece: Some(piece),
+            comment: "T-spin setup".to_string(),
+            ..Default::default()
+        };
+        let mut field = FumenField::default();
+        field.put(&piece);
+        let second = FumenPage {
+            field,
+            comment: first.comment.clone(),
+            colorize: false,
+            ..Default::default()
+        };
+
+        let pages = vec![first, second];
+        assert_eq!(decode(&encode(&pages)).unwrap(), pages);
+    }
+
+    #[test]
+    fn malformed_data_returns_errors() {
+        assert_eq!(decode("v110@vhAAgH"), Err(FumenError::UnsupportedVersion));
+        assert_eq!(
+            decode("v115@vh!AgH"),
+            Err(FumenError::InvalidCharacter('!'))
+        );
+        assert_eq!(decode("v115@vhAAg"), Err(FumenError::UnexpectedEnd));
+        assert_eq!(decode("v115@//"), Err(FumenError::InvalidCell(9)));
+        assert_eq!(
+            decode("v115@vhAAgWUAFLDmClcJSAV"),
+            Err(FumenError::UnexpectedEnd)
+        );
+        assert!(matches!(
+            "#Q=[TT](I)".parse::<FumenQuiz>(),
+            Err(FumenError::InvalidQuiz(_))
+        ));
+        assert!(matches!(
+            "#Q=[T](X)".parse::<FumenQuiz>(),
+            Err(FumenError::InvalidQuiz(_))
+        ));
+    }
+
+    #[test]
+    fn records_a_page_per_placed_piece() {
+        use bevy::ecs::system::RunSystemOnce;
+
+        use crate::board::spin::Spin;
+
+        let mut world = World::new();
+        world.init_resource::<Messages<TetrominoPlaced>>();
+        let board = world
+            .spawn((
+                FumenReplay::default(),
+                Tilemap {
+                    size: uvec2(10, 20),
+                    tile_size: uvec2(8, 8),
+                    buffer_height: 20,
+                    visible_buffer_height: 2,
+                },
+            ))
+            .id();
+        let piece = FumenPiece {
+            kind: TetrominoKind::T,
+            rotation: 0,
+            pos: ivec2(4, 1),
+        };
+        let garbage = PlacedTile {
+            kind: PlacedTileKind::Garbage,
+            piece: None,
+        };
+        world.spawn((
+            Tile {
+                pos: vec2(0.0, 0.0),
+                tilemap: board,
+            },
+            garbage,
+        ));
+        // The placed piece's tiles, already spawned
+        for offset in get_tetromino_shape(piece.kind, piece.rotation) {
+            let tile = PlacedTile {
+                kind: PlacedTileKind::Tetromino(piece.kind),
+                piece: Some(0),
+            };
+            world.spawn((
+                Tile {
+                    pos: (piece.pos + offset).as_vec2(),
+                    tilemap: board,
+                },
+                tile,
+            ));
+        }
+        world.write_message(TetrominoPlaced {
+            board,
+            spin: Spin::None,
+            kind: piece.kind,
+            rotation: piece.rotation,
+            pos: piece.pos,
+        });
+
+        world.run_system_once(record_fumen_pages).unwrap();
+        let pages = &world.get::<FumenReplay>(board).unwrap().0;
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].piece, Some(piece));
+        assert_eq!(pages[0].field.get(ivec2(0, 0)), FumenCell::Garbage);
+        assert_eq!(pages[0].field.get(piece.pos), FumenCell::Empty);
+        assert_eq!(decode(&encode(pages)).unwrap(), *pages);
+    }
+}
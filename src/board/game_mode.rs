@@ -0,0 +1,242 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumCount, EnumIter, EnumString};
+
+use crate::board::{
+    GameEnded, GameOver, GameOverReason, LinesCleared, TetrominoPlaced,
+    line_clear::LineClearSystems,
+    spin::Spin,
+    stats::{BoardStats, ClearKind, StatsDisplaySystems, StatsSystems},
+};
+
+const LINES_PER_LEVEL: u32 = 10;
+const MAX_LEVEL: u32 = 20;
+const MARATHON_MAX_LEVEL: u32 = 15;
+const COMBO_SCORE: u64 = 50;
+
+pub struct GameModePlugin;
+
+impl Plugin for GameModePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (
+                apply_mode_scoring
+                    .after(LineClearSystems)
+                    .before(StatsSystems),
+                (update_mode_levels, check_mode_goals)
+                    .chain()
+                    .in_set(GameModeSystems)
+                    .after(StatsSystems)
+                    .before(StatsDisplaySystems),
+            ),
+        );
+    }
+}
+
+#[derive(SystemSet, Hash, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameModeSystems;
+
+#[derive(
+    Component,
+    EnumIter,
+    EnumCount,
+    EnumString,
+    Display,
+    Serialize,
+    Deserialize,
+    Copy,
+    Clone,
+    Debug,
+    Default,
+    Hash,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+)]
+#[strum(ascii_case_insensitive)]
+#[require(ModeProgress)]
+pub enum GameMode {
+    #[default]
+    Endless,
+    Marathon,
+    Sprint,
+    Ultra,
+}
+
+impl GameMode {
+    pub fn line_goal(self) -> Option<u32> {
+        match self {
+            GameMode::Marathon => Some(MARATHON_MAX_LEVEL * LINES_PER_LEVEL),
+            GameMode::Sprint => Some(40),
+            GameMode::Endless | GameMode::Ultra => None,
+        }
+    }
+
+    pub fn time_limit(self) -> Option<Duration> {
+        match self {
+            GameMode::Ultra => Some(Duration::from_secs(120)),
+            GameMode::Endless | GameMode::Marathon | GameMode::Sprint => None,
+        }
+    }
+
+    pub fn max_level(self) -> u32 {
+        match self {
+            GameMode::Endless => MAX_LEVEL,
+            GameMode::Marathon => MARATHON_MAX_LEVEL,
+            GameMode::Sprint | GameMode::Ultra => 1,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct ModeProgress {
+    pub score: u64,
+    pub level: u32,
+}
+
+impl Default for ModeProgress {
+    fn default() -> Self {
+        Self { score: 0, level: 1 }
+    }
+}
+
+/// Points for a clear before the level multiplier.
+pub fn get_clear_score(kind: ClearKind) -> u64 {
+    match kind {
+        ClearKind::Single => 100,
+        ClearKind::Double => 300,
+        ClearKind::Triple => 500,
+        ClearKind::Tetris => 800,
+        ClearKind::TSpinMini => 100,
+        ClearKind::TSpinMiniSingle => 200,
+        ClearKind::TSpinMiniDouble => 400,
+        ClearKind::TSpin => 400,
+        ClearKind::TSpinSingle => 800,
+        ClearKind::TSpinDouble => 1200,
+        ClearKind::TSpinTriple => 1600,
+    }
+}
+
+pub fn get_perfect_clear_score(count: u32, back_to_back: bool) -> u64 {
+    match count {
+        1 => 800,
+        2 => 1200,
+        3 => 1800,
+        _ if back_to_back => 3200,
+        _ => 2000,
+    }
+}
+
+/// Points for a clear made by tiles falling, before the level multiplier. Each step of a chain is
+/// worth more than the last.
+pub fn get_chain_score(count: u32, chain: u32, perfect_clear: bool) -> u64 {
+    let mut score =
+        ClearKind::new(count, Spin::None).map_or(0, get_clear_score) * (chain as u64 + 1);
+    if perfect_clear {
+        score += get_perfect_clear_score(count, false);
+    }
+    score
+}
+
+// ========== Systems ==========
+
+/// Runs before the board's stats are updated, so the combo and back to back state still describe
+/// the previous placements.
+fn apply_mode_scoring(
+    mut boards: Query<(&mut ModeProgress, &BoardStats)>,
+    mut placed_messages: MessageReader<TetrominoPlaced>,
+    mut lines_cleared_messages: MessageReader<LinesCleared>,
+) {
+    let lines_cleared: Vec<_> = lines_cleared_messages.read().collect();
+
+    for message in placed_messages.read() {
+        let Ok((mut progress, stats)) = boards.get_mut(message.board) else {
+            continue;
+        };
+
+        let (count, spin, perfect_clear) = match lines_cleared
+            .iter()
+            .find(|cleared| cleared.board == message.board && cleared.chain == 0)
+        {
+            Some(cleared) => (cleared.count, cleared.spin, cleared.perfect_clear),
+            None => (0, message.spin, false),
+        };
+        let Some(kind) = ClearKind::new(count, spin) else {
+            continue;
+        };
+
+        let level = progress.level as u64;
+        let back_to_back = count > 0 && stats.back_to_back && kind.is_difficult();
+        let mut score = get_clear_score(kind) * level;
+        if back_to_back {
+            score = score * 3 / 2;
+        }
+        if count > 0 {
+            score += COMBO_SCORE * stats.combo as u64 * level;
+        }
+        if perfect_clear {
+            score += get_perfect_clear_score(count, back_to_back && spin == Spin::None) * level;
+        }
+        progress.score += score;
+    }
+
+    for cleared in lines_cleared.iter().filter(|cleared| cleared.chain > 0) {
+        let Ok((mut progress, _)) = boards.get_mut(cleared.board) else {
+            continue;
+        };
+        progress.score += get_chain_score(cleared.count, cleared.chain, cleared.perfect_clear)
+            * progress.level as u64;
+    }
+}
+
+fn update_mode_levels(mut boards: Query<(&GameMode, &mut ModeProgress, &BoardStats)>) {
+    for (mode, mut progress, stats) in boards.iter_mut() {
+        let level = (1 + stats.lines / LINES_PER_LEVEL).min(mode.max_level());
+        if progress.level != level {
+            progress.level = level;
+        }
+    }
+}
+
+fn check_mode_goals(
+    mut commands: Commands,
+    boards: Query<(Entity, &GameMode, &BoardStats), Without<GameEnded>>,
+    mut game_over_messages: MessageWriter<GameOver>,
+) {
+    for (board_entity, mode, stats) in boards.iter() {
+        let reached_line_goal = mode.line_goal().is_some_and(|goal| stats.lines >= goal);
+        let reached_time_limit = mode.time_limit().is_some_and(|limit| stats.time >= limit);
+
+        if reached_line_goal || reached_time_limit {
+            commands.entity(board_entity).insert(GameEnded);
+            game_over_messages.write(GameOver {
+                board: board_entity,
+                reason: GameOverReason::Completed,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chains_multiply_the_clear_score() {
+        assert_eq!(get_chain_score(1, 0, false), 100);
+        assert_eq!(get_chain_score(1, 1, false), 200);
+        assert_eq!(get_chain_score(2, 2, false), 900);
+        assert_eq!(get_chain_score(4, 3, false), 3200);
+        assert_eq!(get_chain_score(0, 1, false), 0);
+    }
+
+    #[test]
+    fn chain_perfect_clears_are_not_multiplied() {
+        assert_eq!(get_chain_score(1, 1, true), 200 + 800);
+        assert_eq!(get_chain_score(4, 2, true), 2400 + 2000);
+    }
+}
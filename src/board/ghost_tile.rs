@@ -2,8 +2,10 @@ use bevy::{ecs::query::QueryFilter, prelude::*};
 
 use crate::{
     board::{
-        Board, BoardUpdateSystems, RemoveSkipUpdateSystems, SkipUpdate, placed_tile::PlacedTile,
-        tetromino_data::get_tetromino_shape, tile_assets::TileOutlineImages,
+        Board, BoardUpdateSystems, RemoveSkipUpdateSystems, SkipUpdate,
+        placed_tile::PlacedTile,
+        tetromino_data::get_tetromino_shape,
+        tile_assets::{GhostTileImages, get_tile_image},
     },
     tiles::{Tile, Tilemap},
 };
@@ -30,7 +32,7 @@ pub fn spawn_ghost_tiles(
     commands: &mut Commands,
     board: &Board,
     board_entity: Entity,
-    tile_outline_images: &Res<TileOutlineImages>,
+    ghost_tile_images: &Res<GhostTileImages>,
 ) {
     for (index, offset) in get_tetromino_shape(board.kind, board.rotation)
         .iter()
@@ -47,7 +49,7 @@ pub fn spawn_ghost_tiles(
                 offset_index: index,
             },
             ChildOf(board_entity),
-            Sprite::from_image(tile_outline_images.0[&board.kind].clone()),
+            Sprite::from_image(get_tile_image(&ghost_tile_images.0, board.kind)),
             Transform::from_translation(Vec3::new(0.0, 0.0, 2.0)),
         ));
     }
@@ -2,20 +2,25 @@ use bevy::prelude::*;
 
 use crate::{
     board::{
-        BoardUpdateSystems, HoldPieceChanged,
+        Board, BoardUpdateSystems, HoldPieceChanged,
+        board_config::BoardConfig,
         tetromino_data::{TetrominoKind, get_tetromino_display_offset, get_tetromino_shape},
-        tile_assets::TileImages,
+        tile_assets::{TileImages, get_tile_image},
     },
     tiles::{Tile, TileUpdateSystems},
 };
 
+/// Tint for the held piece while hold can't be used.
+const UNAVAILABLE_COLOR: Color = Color::srgb(0.35, 0.35, 0.35);
+
 pub struct HoldDisplayPlugin;
 
 impl Plugin for HoldDisplayPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
             FixedUpdate,
-            update_hold_displays
+            (update_hold_displays, apply_hold_availability_visuals)
+                .chain()
                 .after(BoardUpdateSystems)
                 .before(TileUpdateSystems),
         );
@@ -31,11 +36,14 @@ impl HoldDisplay {
     fn update_display(
         commands: &mut Commands,
         self_entity: Entity,
-        kind: TetrominoKind,
+        kind: Option<TetrominoKind>,
         tile_images: &Res<TileImages>,
         tiles: Query<(Entity, &Tile), With<HoldDisplayTile>>,
     ) {
         HoldDisplay::clear_display(commands, self_entity, tiles);
+        let Some(kind) = kind else {
+            return;
+        };
         let display_offset = get_tetromino_display_offset(kind, 0, uvec2(4, 4));
 
         for offset in get_tetromino_shape(kind, 0).iter() {
@@ -47,7 +55,7 @@ impl HoldDisplay {
                 },
                 HoldDisplayTile,
                 ChildOf(self_entity),
-                Sprite::from_image(tile_images.0[&kind].clone()),
+                Sprite::from_image(get_tile_image(&tile_images.0, kind)),
             ));
         }
     }
@@ -89,3 +97,28 @@ fn update_hold_displays(
         }
     }
 }
+
+fn apply_hold_availability_visuals(
+    displays: Query<&HoldDisplay>,
+    boards: Query<(&Board, &BoardConfig)>,
+    mut tiles: Query<(&Tile, &mut Sprite), With<HoldDisplayTile>>,
+) {
+    for (tile, mut sprite) in tiles.iter_mut() {
+        let Ok(display) = displays.get(tile.tilemap) else {
+            continue;
+        };
+        let Ok((board, board_config)) = boards.get(display.board) else {
+            bevy::log::error_once!("Failed to get board in apply_hold_availability_visuals");
+            continue;
+        };
+
+        let color = if board.can_hold(board_config.hold_mode) {
+            Color::WHITE
+        } else {
+            UNAVAILABLE_COLOR
+        };
+        if sprite.color != color {
+            sprite.color = color;
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use bevy::{platform::collections::HashSet, prelude::*, sprite::Anchor};
+
+use crate::board::{
+    TetrominoPlaced,
+    game_mode::{GameMode, ModeProgress},
+    personal_best::PersonalBestTracker,
+    stats::{BoardStats, StatsDisplaySystems, format_time},
+};
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (update_timer_huds, update_score_huds).in_set(StatsDisplaySystems),
+        );
+    }
+}
+
+/// Timer, personal best and speed stats, shown under the hold display.
+#[derive(Component)]
+#[require(
+    Text2d,
+    Anchor::TOP_RIGHT,
+    TextLayout::new_with_justify(Justify::Right)
+)]
+pub struct TimerHud {
+    pub board: Entity,
+}
+
+/// Score, level and lines, shown under the queue display. Only redrawn when a piece is placed.
+#[derive(Component)]
+#[require(Text2d, Anchor::TOP_LEFT, TextLayout::new_with_justify(Justify::Left))]
+pub struct ScoreHud {
+    pub board: Entity,
+}
+
+// ========== Systems ==========
+
+fn update_timer_huds(
+    mut huds: Query<(&TimerHud, &mut Text2d)>,
+    boards: Query<(Ref<BoardStats>, &GameMode, Option<&PersonalBestTracker>)>,
+) {
+    for (hud, mut text) in huds.iter_mut() {
+        let Ok((stats, mode, tracker)) = boards.get(hud.board) else {
+            bevy::log::error_once!("Failed to get board stats in update_timer_huds");
+            continue;
+        };
+        if !stats.is_changed() {
+            continue;
+        }
+
+        let personal_best = match tracker {
+            Some(tracker) if tracker.beaten => "\nNew PB!".to_string(),
+            Some(tracker) => tracker
+                .best
+                .as_ref()
+                .map(|best| format!("\nPB {}", best.label(*mode)))
+                .unwrap_or_default(),
+            None => String::new(),
+        };
+
+        text.0 = format!(
+            "{mode}\n{}{personal_best}\n\nPPS {:.2}\nKPP {:.2}\nAPM {:.1}\nAttack {}",
+            format_time(stats.time),
+            stats.pieces_per_second(),
+            stats.keys_per_piece(),
+            stats.attack_per_minute(),
+            stats.attack,
+        );
+    }
+}
+
+fn update_score_huds(
+    mut huds: Query<(Ref<ScoreHud>, &mut Text2d)>,
+    boards: Query<(&BoardStats, &GameMode, &ModeProgress)>,
+    mut placed_messages: MessageReader<TetrominoPlaced>,
+) {
+    let placed_boards: HashSet<Entity> = placed_messages
+        .read()
+        .map(|message| message.board)
+        .collect();
+
+    for (hud, mut text) in huds.iter_mut() {
+        if !hud.is_added() && !placed_boards.contains(&hud.board) {
+            continue;
+        }
+        let Ok((stats, mode, progress)) = boards.get(hud.board) else {
+            bevy::log::error_once!("Failed to get board stats in update_score_huds");
+            continue;
+        };
+
+        let lines = match mode.line_goal() {
+            Some(goal) => format!("{}/{goal}", stats.lines),
+            None => stats.lines.to_string(),
+        };
+        text.0 = format!(
+            "Score\n{}\n\nLevel\n{}\n\nLines\n{lines}",
+            progress.score, progress.level
+        );
+    }
+}
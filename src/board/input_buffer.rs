@@ -0,0 +1,56 @@
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::ActionState;
+
+use crate::{
+    board::{
+        Board, BoardUpdateSystems, GameEnded, SkipUpdate, board_config::BoardConfig,
+        editor::BoardEditor, get_rotation_direction, get_shift_direction,
+        tetromino_data::TetrominoRotation,
+    },
+    input::Action,
+};
+
+pub struct InputBufferPlugin;
+
+impl Plugin for InputBufferPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, buffer_inputs.before(BoardUpdateSystems));
+    }
+}
+
+/// Inputs made while a board is paused for a delay. Hold and rotation are applied when play
+/// resumes, and a held shift keeps charging DAS in the meantime.
+#[derive(Component, Default)]
+pub struct InputBuffer {
+    pub rotation: Option<TetrominoRotation>,
+    pub hold: bool,
+}
+
+fn buffer_inputs(
+    mut boards: Query<
+        (
+            &ActionState<Action>,
+            &mut Board,
+            &mut InputBuffer,
+            &BoardConfig,
+        ),
+        (With<SkipUpdate>, Without<BoardEditor>, Without<GameEnded>),
+    >,
+    time: Res<Time>,
+) {
+    for (action_state, mut board, mut input_buffer, board_config) in boards.iter_mut() {
+        if let Some(direction) = get_rotation_direction(action_state) {
+            input_buffer.rotation = Some(direction);
+        }
+        if action_state.just_pressed(&Action::Hold) {
+            input_buffer.hold = true;
+        }
+
+        let shift = get_shift_direction(action_state);
+        board.set_auto_shift_direction(shift);
+        if shift != 0 {
+            board.auto_shift_charge =
+                (board.auto_shift_charge + time.delta()).min(board_config.auto_shift_delay());
+        }
+    }
+}
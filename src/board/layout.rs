@@ -0,0 +1,173 @@
+use bevy::{platform::collections::HashMap, prelude::*, window::PrimaryWindow};
+
+use crate::{
+    board::{
+        Board, BoardGroup,
+        hold_display::HoldDisplay,
+        hud::{ScoreHud, TimerHud},
+        queue_display::QueueDisplay,
+    },
+    tiles::Tilemap,
+};
+
+/// Space between a board and the displays beside it, in tiles.
+const GAP: f32 = 1.0;
+/// Space kept free around each board group, in tiles.
+const MARGIN: f32 = 1.0;
+/// HUD text size per unit of scale, so the HUD grows with the board.
+const HUD_FONT_SIZE: f32 = 5.0;
+
+pub struct LayoutPlugin;
+
+impl Plugin for LayoutPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, apply_layout);
+    }
+}
+
+/// Positions relative to a board's centre, in texture pixels before scaling.
+struct GroupLayout {
+    hold: Vec2,
+    queue: Vec2,
+    timer_hud: Vec2,
+    score_hud: Vec2,
+    /// Space the group needs with the board centred in it.
+    size: Vec2,
+}
+
+fn get_group_layout(board: &Tilemap, hold: &Tilemap, queue: &Tilemap) -> GroupLayout {
+    let tile_size = board.tile_size.as_vec2();
+    let gap = GAP * tile_size;
+    let board_half_size = (board.size * board.tile_size).as_vec2() / 2.0;
+    let hold_size = (hold.size * hold.tile_size).as_vec2();
+    let queue_size = (queue.size * queue.tile_size).as_vec2();
+
+    // Displays sit beside the board, level with its top
+    let hold_pos = vec2(
+        -board_half_size.x - gap.x - hold_size.x / 2.0,
+        board_half_size.y - hold_size.y / 2.0,
+    );
+    let queue_pos = vec2(
+        board_half_size.x + gap.x + queue_size.x / 2.0,
+        board_half_size.y - queue_size.y / 2.0,
+    );
+
+    let half_width = board_half_size.x + gap.x + hold_size.x.max(queue_size.x);
+    let peek_height = (board.visible_buffer_height * board.tile_size.y) as f32;
+    let half_height = (board_half_size.y + peek_height).max(queue_size.y - board_half_size.y);
+    let margin = MARGIN * tile_size;
+
+    GroupLayout {
+        hold: hold_pos,
+        queue: queue_pos,
+        timer_hud: vec2(
+            -board_half_size.x - gap.x,
+            hold_pos.y - hold_size.y / 2.0 - gap.y,
+        ),
+        score_hud: vec2(board_half_size.x + gap.x, queue_pos.y - queue_size.y / 2.0),
+        size: vec2(half_width, half_height) * 2.0 + margin * 2.0,
+    }
+}
+
+/// The largest whole number scale that fits `size` into `space`, so pixels stay square.
+fn get_scale(size: Vec2, space: Vec2) -> f32 {
+    (space / size).min_element().floor().max(1.0)
+}
+
+// ========== Systems ==========
+
+/// Lays board groups out side by side, each scaled to fill its share of the window. Runs again
+/// when a queue display is resized.
+#[allow(clippy::type_complexity)]
+fn apply_layout(
+    window: Single<Ref<Window>, With<PrimaryWindow>>,
+    mut groups: Query<(Entity, &mut Transform), With<BoardGroup>>,
+    added_groups: Query<(), Added<BoardGroup>>,
+    mut removed_groups: RemovedComponents<BoardGroup>,
+    resized_queues: Query<(), (Changed<Tilemap>, With<QueueDisplay>)>,
+    mut boards: Query<
+        (Entity, &Tilemap, &ChildOf, &mut Transform),
+        (With<Board>, Without<BoardGroup>),
+    >,
+    hold_tilemaps: Query<(&HoldDisplay, &Tilemap)>,
+    queue_tilemaps: Query<(&QueueDisplay, &Tilemap)>,
+    mut displays: Query<
+        (
+            AnyOf<(&HoldDisplay, &QueueDisplay, &TimerHud, &ScoreHud)>,
+            &mut Transform,
+            Option<&mut TextFont>,
+        ),
+        (Without<Board>, Without<BoardGroup>),
+    >,
+) {
+    let removed = removed_groups.read().count() > 0;
+    if !window.is_changed() && added_groups.is_empty() && resized_queues.is_empty() && !removed {
+        return;
+    }
+
+    let window_size = window.size();
+    let mut group_entities: Vec<Entity> = groups.iter().map(|(entity, _)| entity).collect();
+    group_entities.sort_by_key(|group| group.index());
+    let cell_width = window_size.x / group_entities.len().max(1) as f32;
+
+    let mut layouts = HashMap::new();
+    for (board_entity, tilemap, child_of, mut board_transform) in boards.iter_mut() {
+        let Some(index) = group_entities
+            .iter()
+            .position(|group| *group == child_of.parent())
+        else {
+            continue;
+        };
+        let Some((_, hold_tilemap)) = hold_tilemaps
+            .iter()
+            .find(|(hold, _)| hold.board == board_entity)
+        else {
+            bevy::log::error_once!("Failed to get hold display in apply_layout");
+            continue;
+        };
+        let Some((_, queue_tilemap)) = queue_tilemaps
+            .iter()
+            .find(|(queue, _)| queue.board == board_entity)
+        else {
+            bevy::log::error_once!("Failed to get queue display in apply_layout");
+            continue;
+        };
+
+        let layout = get_group_layout(tilemap, hold_tilemap, queue_tilemap);
+        let scale = get_scale(layout.size, vec2(cell_width, window_size.y));
+
+        if let Ok((_, mut group_transform)) = groups.get_mut(child_of.parent()) {
+            let x = -window_size.x / 2.0 + cell_width * (index as f32 + 0.5);
+            group_transform.translation = vec3(x.round(), 0.0, 0.0);
+        }
+        *board_transform = Transform::from_scale(Vec3::splat(scale));
+        layouts.insert(board_entity, (layout, scale));
+    }
+
+    for (display, mut transform, font) in displays.iter_mut() {
+        let board_entity = match display {
+            (Some(hold), ..) => hold.board,
+            (_, Some(queue), ..) => queue.board,
+            (_, _, Some(hud), _) => hud.board,
+            (_, _, _, Some(hud)) => hud.board,
+            _ => continue,
+        };
+        let Some((layout, scale)) = layouts.get(&board_entity) else {
+            continue;
+        };
+        let (pos, scaled) = match display {
+            (Some(_), ..) => (layout.hold, true),
+            (_, Some(_), ..) => (layout.queue, true),
+            (_, _, Some(_), _) => (layout.timer_hud, false),
+            _ => (layout.score_hud, false),
+        };
+
+        transform.translation = (pos * scale).extend(transform.translation.z);
+        if scaled {
+            transform.scale = Vec3::splat(*scale);
+        } else if let Some(mut font) = font {
+            // Text is redrawn at the new size rather than scaled, so it stays sharp
+            font.font_size = HUD_FONT_SIZE * scale;
+        }
+    }
+}
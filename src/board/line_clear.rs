@@ -1,9 +1,10 @@
-use bevy::prelude::*;
+use bevy::{platform::collections::HashMap, prelude::*};
 
 use crate::{
     board::{
-        AddSkipUpdateSystems, Board, BoardUpdateSystems, RemoveSkipUpdateSystems, SkipUpdate,
-        board_config::BoardConfig, placed_tile::PlacedTile,
+        AddSkipUpdateSystems, Board, BoardUpdateSystems, LinesCleared, LinesCollapsed,
+        RemoveSkipUpdateSystems, SkipUpdate, TetrominoPlaced, board_config::BoardConfig,
+        placed_tile::PlacedTile, spin::Spin, tile_assets::LineClearImage,
     },
     tiles::{Tile, Tilemap},
 };
@@ -12,21 +13,116 @@ pub struct LineClearPlugin;
 
 impl Plugin for LineClearPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup).add_systems(
+        app.add_systems(
             FixedUpdate,
             (
                 clear_lines
+                    .in_set(LineClearSystems)
                     .after(BoardUpdateSystems)
                     .before(RemoveSkipUpdateSystems),
-                (apply_line_clear_lifetime, apply_line_clear_visuals).after(clear_lines),
+                spawn_line_clear_tiles
+                    .after(clear_lines)
+                    .before(AddSkipUpdateSystems),
+                (apply_line_clear_lifetime, apply_line_clear_visuals).after(spawn_line_clear_tiles),
                 apply_line_clear_skip_update.in_set(AddSkipUpdateSystems),
             ),
         );
     }
 }
 
-#[derive(Resource)]
-pub struct LineClearSprite(Handle<Image>);
+#[derive(SystemSet, Hash, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineClearSystems;
+
+/// Rows cleared on a board, waiting for the line clear delay to end before collapsing.
+#[derive(Component)]
+pub struct ClearedRows {
+    pub rows: Vec<u32>,
+    /// 0 for a clear made by placing a piece, counting up for each clear that follows from
+    /// tiles falling.
+    pub chain: u32,
+}
+
+/// Where each tile lands when groups of connected tiles fall until they rest on the floor or on
+/// another tile. Lower groups fall first, so groups stack in the order they land.
+pub fn get_fallen_positions(
+    positions: &[IVec2],
+    is_connected: impl Fn(usize, usize) -> bool,
+) -> Vec<IVec2> {
+    let indices: HashMap<IVec2, usize> = positions
+        .iter()
+        .enumerate()
+        .map(|(index, pos)| (*pos, index))
+        .collect();
+
+    let mut group_of: Vec<Option<usize>> = vec![None; positions.len()];
+    let mut groups: Vec<Vec<usize>> = vec![];
+    for start in 0..positions.len() {
+        if group_of[start].is_some() {
+            continue;
+        }
+        let mut group = vec![start];
+        group_of[start] = Some(groups.len());
+        let mut next = 0;
+        while next < group.len() {
+            let index = group[next];
+            next += 1;
+            for offset in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+                if let Some(&neighbour) = indices.get(&(positions[index] + offset))
+                    && group_of[neighbour].is_none()
+                    && is_connected(index, neighbour)
+                {
+                    group_of[neighbour] = Some(groups.len());
+                    group.push(neighbour);
+                }
+            }
+        }
+        groups.push(group);
+    }
+    // Every tile is in a group by now
+    let group_of: Vec<usize> = group_of.into_iter().flatten().collect();
+
+    let mut fallen = positions.to_vec();
+    let mut moved = true;
+    while moved {
+        moved = false;
+        groups.sort_by_key(|group| group.iter().map(|index| fallen[*index].y).min());
+        let mut occupied: HashMap<IVec2, usize> = fallen
+            .iter()
+            .enumerate()
+            .map(|(index, pos)| (*pos, group_of[index]))
+            .collect();
+
+        for group in groups.iter() {
+            let group_index = group_of[group[0]];
+            let can_fall = |distance: i32| {
+                group.iter().all(|index| {
+                    let pos = fallen[*index] - ivec2(0, distance);
+                    pos.y >= 0
+                        && occupied
+                            .get(&pos)
+                            .is_none_or(|other_group| *other_group == group_index)
+                })
+            };
+            let mut distance = 0;
+            while can_fall(distance + 1) {
+                distance += 1;
+            }
+            if distance == 0 {
+                continue;
+            }
+
+            moved = true;
+            for index in group {
+                occupied.remove(&fallen[*index]);
+            }
+            for index in group {
+                fallen[*index].y -= distance;
+                occupied.insert(fallen[*index], group_index);
+            }
+        }
+    }
+    fallen
+}
 
 #[derive(Component)]
 pub struct LineClearTile {
@@ -34,11 +130,6 @@ pub struct LineClearTile {
     pub lifetime: i32,
 }
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let line_clear_sprite: Handle<Image> = asset_server.load("tiles/line_clear.png");
-    commands.insert_resource(LineClearSprite(line_clear_sprite));
-}
-
 fn apply_line_clear_skip_update(
     mut commands: Commands,
     line_clear_tiles: Query<&Tile, With<LineClearTile>>,
@@ -68,14 +159,37 @@ fn apply_line_clear_visuals(mut line_clear_tiles: Query<(&mut Transform, &LineCl
     }
 }
 
+/// Clears full lines after a piece is placed, and after tiles fall into place from an earlier
+/// clear.
 fn clear_lines(
     mut commands: Commands,
-    mut boards: Query<(Entity, &Tilemap, &BoardConfig), (With<Board>, Without<SkipUpdate>)>,
+    boards: Query<&Tilemap, With<Board>>,
+    mut placed_messages: MessageReader<TetrominoPlaced>,
+    mut collapsed_messages: MessageReader<LinesCollapsed>,
     placed_tiles: Query<(Entity, &Tile), With<PlacedTile>>,
-    line_clear_sprite: Res<LineClearSprite>,
+    mut lines_cleared_messages: MessageWriter<LinesCleared>,
 ) {
-    for (board_entity, tilemap, board_config) in boards.iter_mut() {
-        for y in 0..tilemap.size.y as i32 {
+    // (board, spin, chain)
+    let mut checks: Vec<(Entity, Spin, u32)> = placed_messages
+        .read()
+        .map(|message| (message.board, message.spin, 0))
+        .collect();
+    for message in collapsed_messages.read() {
+        // A piece placed in the same tick already checks the whole board
+        if !checks.iter().any(|(board, ..)| *board == message.board) {
+            checks.push((message.board, Spin::None, message.chain + 1));
+        }
+    }
+
+    for (board_entity, spin, chain) in checks {
+        let Ok(tilemap) = boards.get(board_entity) else {
+            bevy::log::error_once!("Failed to get board when clearing lines!");
+            continue;
+        };
+
+        let mut rows: Vec<u32> = vec![];
+        let mut num_cleared_tiles = 0;
+        for y in 0..tilemap.total_height() as i32 {
             let mut clear_line = true;
             let mut tiles_to_clear: Vec<Entity> = vec![];
 
@@ -92,27 +206,180 @@ fn clear_lines(
             }
 
             if clear_line {
+                rows.push(y as u32);
+                num_cleared_tiles += tiles_to_clear.len();
                 for tile_entity in tiles_to_clear {
                     commands.entity(tile_entity).despawn();
                 }
+            }
+        }
 
-                for x in 0..tilemap.size.x as i32 {
-                    commands.spawn((
-                        Name::new("LineClearTile"),
-                        Tile {
-                            pos: ivec2(x, y).as_vec2(),
-                            tilemap: board_entity,
-                        },
-                        LineClearTile {
-                            fade_time: board_config.line_clear_fade_time,
-                            lifetime: board_config.line_clear_delay
-                                + board_config.line_clear_horizontal_delay * x,
-                        },
-                        ChildOf(board_entity),
-                        Sprite::from_image(line_clear_sprite.0.clone()),
-                    ));
-                }
+        if !rows.is_empty() {
+            let num_board_tiles = placed_tiles
+                .iter()
+                .filter(|(_, tile)| tile.tilemap == board_entity)
+                .count();
+            commands.entity(board_entity).insert(ClearedRows {
+                rows: rows.clone(),
+                chain,
+            });
+            lines_cleared_messages.write(LinesCleared {
+                board: board_entity,
+                count: rows.len() as u32,
+                rows,
+                chain,
+                spin,
+                perfect_clear: num_board_tiles == num_cleared_tiles,
+            });
+        }
+    }
+}
+
+fn spawn_line_clear_tiles(
+    mut commands: Commands,
+    boards: Query<(&Tilemap, &BoardConfig), With<Board>>,
+    line_clear_image: Res<LineClearImage>,
+    mut lines_cleared_messages: MessageReader<LinesCleared>,
+) {
+    for message in lines_cleared_messages.read() {
+        let Ok((tilemap, board_config)) = boards.get(message.board) else {
+            bevy::log::error_once!("Failed to get board when spawning line clear tiles!");
+            continue;
+        };
+
+        for y in message.rows.iter() {
+            for x in 0..tilemap.size.x as i32 {
+                commands.spawn((
+                    Name::new("LineClearTile"),
+                    Tile {
+                        pos: ivec2(x, *y as i32).as_vec2(),
+                        tilemap: message.board,
+                    },
+                    LineClearTile {
+                        fade_time: board_config.line_clear_fade_time,
+                        lifetime: board_config.line_clear_delay
+                            + board_config.line_clear_horizontal_delay * x,
+                    },
+                    ChildOf(message.board),
+                    Sprite::from_image(line_clear_image.0.clone()),
+                ));
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{placed_tile::PlacedTileKind, tetromino_data::TetrominoKind};
+
+    fn piece_tile(piece: u32) -> PlacedTile {
+        PlacedTile {
+            kind: PlacedTileKind::Tetromino(TetrominoKind::T),
+            piece: Some(piece),
+        }
+    }
+
+    /// Falls with sticky gravity, where each tile is given as its position and piece.
+    fn get_sticky_fallen_positions(tiles: &[(IVec2, u32)]) -> Vec<IVec2> {
+        let positions: Vec<IVec2> = tiles.iter().map(|(pos, _)| *pos).collect();
+        let placed_tiles: Vec<PlacedTile> =
+            tiles.iter().map(|(_, piece)| piece_tile(*piece)).collect();
+        get_fallen_positions(&positions, |a, b| {
+            placed_tiles[a].is_connected(&placed_tiles[b])
+        })
+    }
+
+    #[test]
+    fn cascade_tiles_fall_into_holes() {
+        let positions = [
+            ivec2(0, 0),
+            ivec2(2, 0),
+            ivec2(1, 3),
+            ivec2(1, 4),
+            ivec2(0, 2),
+        ];
+        let fallen = get_fallen_positions(&positions, |_, _| false);
+        assert_eq!(
+            fallen,
+            [
+                ivec2(0, 0),
+                ivec2(2, 0),
+                ivec2(1, 0),
+                ivec2(1, 1),
+                ivec2(0, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn sticky_pieces_hang_on_overhangs() {
+        let fallen = get_sticky_fallen_positions(&[
+            (ivec2(0, 0), 0),
+            (ivec2(0, 1), 0),
+            (ivec2(0, 4), 1),
+            (ivec2(1, 4), 1),
+            (ivec2(2, 4), 1),
+        ]);
+        assert_eq!(
+            fallen,
+            [
+                ivec2(0, 0),
+                ivec2(0, 1),
+                ivec2(0, 2),
+                ivec2(1, 2),
+                ivec2(2, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn interlocking_groups_land_in_order() {
+        // A hook whose top rests on a bar, so it can only fall once the bar has landed
+        let fallen = get_sticky_fallen_positions(&[
+            (ivec2(0, 3), 0),
+            (ivec2(1, 3), 0),
+            (ivec2(2, 1), 1),
+            (ivec2(2, 2), 1),
+            (ivec2(2, 3), 1),
+            (ivec2(2, 4), 1),
+            (ivec2(1, 4), 1),
+        ]);
+        assert_eq!(
+            fallen,
+            [
+                ivec2(0, 0),
+                ivec2(1, 0),
+                ivec2(2, 0),
+                ivec2(2, 1),
+                ivec2(2, 2),
+                ivec2(2, 3),
+                ivec2(1, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn pieces_split_by_a_cleared_row_fall_separately() {
+        // Piece 0 lost its tile in row 3, leaving one tile below the cleared row and two above
+        let fallen = get_sticky_fallen_positions(&[
+            (ivec2(1, 0), 1),
+            (ivec2(1, 1), 1),
+            (ivec2(1, 2), 1),
+            (ivec2(0, 2), 0),
+            (ivec2(0, 4), 0),
+            (ivec2(1, 4), 0),
+        ]);
+        assert_eq!(
+            fallen,
+            [
+                ivec2(1, 0),
+                ivec2(1, 1),
+                ivec2(1, 2),
+                ivec2(0, 0),
+                ivec2(0, 3),
+                ivec2(1, 3),
+            ]
+        );
+    }
+}
@@ -0,0 +1,418 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    board::{
+        Board, GameOver, GameOverReason,
+        board_config::BoardConfig,
+        game_mode::{GameMode, GameModeSystems, ModeProgress},
+        stats::{BoardStats, StatsDisplaySystems, format_time},
+    },
+    rng::RandomSeed,
+};
+
+pub struct PersonalBestPlugin;
+
+impl Plugin for PersonalBestPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PersonalBests::load()).add_systems(
+            FixedUpdate,
+            (
+                attach_personal_best_trackers,
+                update_personal_best_trackers,
+                record_personal_bests,
+            )
+                .chain()
+                .after(GameModeSystems)
+                .before(StatsDisplaySystems),
+        );
+    }
+}
+
+/// Best runs per mode and settings fingerprint, kept in the user's data directory between
+/// sessions.
+#[derive(Resource, Serialize, Deserialize, Default)]
+pub struct PersonalBests(BTreeMap<GameMode, BTreeMap<String, PersonalBest>>);
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PersonalBest {
+    pub time_ms: u64,
+    pub score: u64,
+    pub lines: u32,
+    pub seed: u64,
+    pub date: String,
+    /// Fingerprint of the board config the run was played with.
+    pub settings: String,
+}
+
+/// Attached to boards with a game mode. Holds the best run on the board's settings from before
+/// this game started.
+#[derive(Component)]
+pub struct PersonalBestTracker {
+    pub best: Option<PersonalBest>,
+    pub beaten: bool,
+}
+
+impl PersonalBests {
+    fn load() -> Self {
+        let Some(path) = get_personal_bests_path() else {
+            return Self::default();
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(data) => ron::from_str(&data).unwrap_or_else(|error| {
+                error!("Failed to parse {}: {error}", path.display());
+                Self::default()
+            }),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(error) => {
+                error!("Failed to read {}: {error}", path.display());
+                Self::default()
+            }
+        }
+    }
+
+    fn save(&self) {
+        let Some(path) = get_personal_bests_path() else {
+            error!("No data directory to save personal bests to");
+            return;
+        };
+
+        let result = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(io::Error::other)
+            .and_then(|data| {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(&path, data)
+            });
+        if let Err(error) = result {
+            error!("Failed to save {}: {error}", path.display());
+        }
+    }
+}
+
+impl PersonalBest {
+    pub fn label(&self, mode: GameMode) -> String {
+        match mode {
+            GameMode::Sprint => format_time(Duration::from_millis(self.time_ms)),
+            GameMode::Marathon => format!("{} ({} lines)", self.score, self.lines),
+            GameMode::Endless | GameMode::Ultra => self.score.to_string(),
+        }
+    }
+
+    pub fn beats(&self, other: &PersonalBest, mode: GameMode) -> bool {
+        match mode {
+            GameMode::Sprint => self.time_ms < other.time_ms,
+            GameMode::Marathon => (self.score, self.lines) > (other.score, other.lines),
+            GameMode::Endless | GameMode::Ultra => self.score > other.score,
+        }
+    }
+}
+
+fn get_personal_bests_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("tetrominoes").join("personal_bests.ron"))
+}
+
+fn get_run(
+    stats: &BoardStats,
+    progress: &ModeProgress,
+    config: &BoardConfig,
+    seed: u64,
+) -> PersonalBest {
+    PersonalBest {
+        time_ms: stats.time.as_millis() as u64,
+        score: progress.score,
+        lines: stats.lines,
+        seed,
+        date: get_date(SystemTime::now()),
+        settings: get_settings_fingerprint(config),
+    }
+}
+
+/// FNV-1a hash of the rule and handling settings, so runs on different settings are kept apart.
+/// Display only settings such as the queue layout and line clear animation are left out.
+fn get_settings_fingerprint(config: &BoardConfig) -> String {
+    let fields = [
+        config.board_width.to_string(),
+        config.board_height.to_string(),
+        config.buffer_height.to_string(),
+        config.auto_shift_delay_ms.to_string(),
+        config.auto_repeat_rate_ms.to_string(),
+        config.das_cut_delay_ms.to_string(),
+        config.das_carry.to_string(),
+        config.lock_delay.to_string(),
+        format!("{:?}", config.lock_mode),
+        config.lock_reset_limit.to_string(),
+        format!("{:?}", config.soft_drop_factor),
+        format!("{:?}", config.hold_mode),
+        format!("{:?}", config.line_clear_gravity),
+        config.queue_hidden_pieces.to_string(),
+        format!("{:?}", config.piece_sequence),
+    ];
+    let hash = fields
+        .join(";")
+        .bytes()
+        .fold(0xcbf29ce484222325_u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+    format!("{hash:016x}")
+}
+
+/// Formats a time as a `YYYY-MM-DD` UTC date.
+fn get_date(time: SystemTime) -> String {
+    let days = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / 86400;
+
+    // Howard Hinnant's civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+// ========== Systems ==========
+
+/// Boards playing a fixed piece sequence are drills, so they get no tracker and never record a
+/// personal best.
+fn attach_personal_best_trackers(
+    mut commands: Commands,
+    boards: Query<(Entity, &GameMode, &Board, &BoardConfig), Added<GameMode>>,
+    personal_bests: Res<PersonalBests>,
+) {
+    for (board_entity, mode, board, config) in boards.iter() {
+        if board.piece_sequence.is_some() {
+            continue;
+        }
+        let best = personal_bests
+            .0
+            .get(mode)
+            .and_then(|bests| bests.get(&get_settings_fingerprint(config)));
+        commands.entity(board_entity).insert(PersonalBestTracker {
+            best: best.cloned(),
+            beaten: false,
+        });
+    }
+}
+
+/// Flags a run as soon as it passes the personal best. Sprint times are only known once the run
+/// is complete, so those are flagged in `record_personal_bests` instead.
+fn update_personal_best_trackers(
+    mut boards: Query<(
+        &GameMode,
+        &BoardStats,
+        &ModeProgress,
+        &mut PersonalBestTracker,
+    )>,
+) {
+    for (mode, stats, progress, mut tracker) in boards.iter_mut() {
+        if tracker.beaten || *mode == GameMode::Sprint {
+            continue;
+        }
+        let Some(best) = &tracker.best else {
+            continue;
+        };
+
+        let run = PersonalBest {
+            time_ms: stats.time.as_millis() as u64,
+            score: progress.score,
+            lines: stats.lines,
+            ..best.clone()
+        };
+        if run.beats(best, *mode) {
+            tracker.beaten = true;
+            warn!("{mode}: new personal best pace!");
+        }
+    }
+}
+
+fn record_personal_bests(
+    mut boards: Query<(
+        &GameMode,
+        &BoardStats,
+        &ModeProgress,
+        &BoardConfig,
+        &mut PersonalBestTracker,
+    )>,
+    mut game_over_messages: MessageReader<GameOver>,
+    mut personal_bests: ResMut<PersonalBests>,
+    seed: Res<RandomSeed>,
+) {
+    for message in game_over_messages.read() {
+        let Ok((mode, stats, progress, config, mut tracker)) = boards.get_mut(message.board) else {
+            continue;
+        };
+        if *mode == GameMode::Sprint && message.reason != GameOverReason::Completed {
+            continue;
+        }
+
+        // Settings can be reloaded mid game, so the run is compared against the best on the
+        // settings it finished with rather than the tracker's
+        let run = get_run(stats, progress, config, seed.0);
+        let bests = personal_bests.0.entry(*mode).or_default();
+        if bests
+            .get(&run.settings)
+            .is_some_and(|best| !run.beats(best, *mode))
+        {
+            continue;
+        }
+
+        info!("{mode}: new personal best {}", run.label(*mode));
+        tracker.beaten = true;
+        bests.insert(run.settings.clone(), run);
+        personal_bests.save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg32;
+
+    use super::*;
+    use crate::board::board_config::QueueLayout;
+
+    fn get_best(time_ms: u64) -> PersonalBest {
+        PersonalBest {
+            time_ms,
+            score: 0,
+            lines: 40,
+            seed: 0,
+            date: "2026-01-01".to_string(),
+            settings: String::new(),
+        }
+    }
+
+    #[test]
+    fn drill_runs_do_not_replace_personal_bests() {
+        let mut world = World::new();
+        world.init_resource::<Messages<GameOver>>();
+        world.insert_resource(RandomSeed(0));
+        let settings = get_settings_fingerprint(&BoardConfig::default());
+        world.insert_resource(PersonalBests(BTreeMap::from([(
+            GameMode::Sprint,
+            BTreeMap::from([(settings.clone(), get_best(60_000))]),
+        )])));
+
+        let piece_sequence = "IIII".parse().unwrap();
+        let board = world
+            .spawn((
+                Board::new(Pcg32::seed_from_u64(0), Some(piece_sequence)),
+                GameMode::Sprint,
+                BoardConfig::default(),
+                BoardStats {
+                    time: Duration::from_secs(10),
+                    lines: 40,
+                    ..default()
+                },
+                ModeProgress::default(),
+            ))
+            .id();
+
+        world
+            .run_system_once(attach_personal_best_trackers)
+            .unwrap();
+        assert!(world.get::<PersonalBestTracker>(board).is_none());
+
+        world.write_message(GameOver {
+            board,
+            reason: GameOverReason::Completed,
+        });
+        world.run_system_once(record_personal_bests).unwrap();
+
+        let personal_bests = world.resource::<PersonalBests>();
+        assert_eq!(
+            personal_bests.0[&GameMode::Sprint][&settings].time_ms,
+            60_000
+        );
+    }
+
+    #[test]
+    fn runs_beat_by_the_mode_goal() {
+        let fast = get_best(50_000);
+        let slow = get_best(60_000);
+        assert!(fast.beats(&slow, GameMode::Sprint));
+        assert!(!slow.beats(&fast, GameMode::Sprint));
+        assert!(!fast.beats(&fast, GameMode::Sprint));
+
+        let high = PersonalBest {
+            score: 2000,
+            lines: 10,
+            ..get_best(0)
+        };
+        let low = PersonalBest {
+            score: 1000,
+            lines: 30,
+            ..get_best(0)
+        };
+        assert!(high.beats(&low, GameMode::Ultra));
+        assert!(high.beats(&low, GameMode::Marathon));
+
+        // Marathon ties on score are broken by lines
+        let more_lines = PersonalBest {
+            lines: 11,
+            ..high.clone()
+        };
+        assert!(more_lines.beats(&high, GameMode::Marathon));
+        assert!(!more_lines.beats(&high, GameMode::Ultra));
+    }
+
+    #[test]
+    fn fingerprints_ignore_display_settings() {
+        let config = BoardConfig::default();
+        let display = BoardConfig {
+            queue_display_length: 6,
+            queue_layout: QueueLayout::Horizontal,
+            line_clear_fade_time: 20,
+            ..default()
+        };
+        let handling = BoardConfig {
+            auto_repeat_rate_ms: 0,
+            ..default()
+        };
+        assert_eq!(
+            get_settings_fingerprint(&config),
+            get_settings_fingerprint(&display)
+        );
+        assert_ne!(
+            get_settings_fingerprint(&config),
+            get_settings_fingerprint(&handling)
+        );
+    }
+
+    #[test]
+    fn dates_are_formatted_in_utc() {
+        assert_eq!(get_date(UNIX_EPOCH), "1970-01-01");
+        assert_eq!(
+            get_date(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "2000-02-29"
+        );
+        assert_eq!(
+            get_date(UNIX_EPOCH + Duration::from_secs(1_735_689_599)),
+            "2024-12-31"
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use std::{fmt, str::FromStr};
+
+use crate::board::tetromino_data::TetrominoKind;
+
+const RANDOM_SUFFIX: &str = "...";
+
+/// A fixed piece order, such as `"TIOLJSZ"`, used ahead of the randomizer. The order repeats
+/// forever unless it ends in `...`, in which case the randomizer takes over once it runs out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PieceSequence {
+    pieces: Vec<TetrominoKind>,
+    repeat: bool,
+    index: usize,
+}
+
+impl Iterator for PieceSequence {
+    type Item = TetrominoKind;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.pieces.len() {
+            if !self.repeat {
+                return None;
+            }
+            self.index = 0;
+        }
+
+        self.index += 1;
+        self.pieces.get(self.index - 1).copied()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PieceSequenceError {
+    Empty,
+    InvalidPiece(char),
+}
+
+impl fmt::Display for PieceSequenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PieceSequenceError::Empty => write!(f, "piece sequence is empty"),
+            PieceSequenceError::InvalidPiece(c) => {
+                write!(f, "'{c}' is not a piece, expected one of IJLOSTZ")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PieceSequenceError {}
+
+impl FromStr for PieceSequence {
+    type Err = PieceSequenceError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let (value, repeat) = match value.strip_suffix(RANDOM_SUFFIX) {
+            Some(value) => (value, false),
+            None => (value, true),
+        };
+
+        let pieces = value
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| {
+                TetrominoKind::from_str(&c.to_string())
+                    .map_err(|_| PieceSequenceError::InvalidPiece(c))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if pieces.is_empty() {
+            return Err(PieceSequenceError::Empty);
+        }
+
+        Ok(Self {
+            pieces,
+            repeat,
+            index: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use TetrominoKind::*;
+
+    #[test]
+    fn repeats_without_suffix() {
+        let sequence: PieceSequence = "TIO".parse().unwrap();
+        assert_eq!(sequence.take(7).collect::<Vec<_>>(), [T, I, O, T, I, O, T]);
+    }
+
+    #[test]
+    fn hands_off_to_the_randomizer_after_suffix() {
+        let mut sequence: PieceSequence = "IJ LS ...".parse().unwrap();
+        assert_eq!(sequence.by_ref().take(4).collect::<Vec<_>>(), [I, J, L, S]);
+        assert_eq!(sequence.next(), None);
+        assert_eq!(sequence.next(), None);
+    }
+
+    #[test]
+    fn rejects_invalid_pieces() {
+        assert_eq!(
+            "TIX".parse::<PieceSequence>(),
+            Err(PieceSequenceError::InvalidPiece('X'))
+        );
+    }
+
+    #[test]
+    fn rejects_empty_sequences() {
+        assert_eq!("".parse::<PieceSequence>(), Err(PieceSequenceError::Empty));
+        assert_eq!(
+            "...".parse::<PieceSequence>(),
+            Err(PieceSequenceError::Empty)
+        );
+    }
+}
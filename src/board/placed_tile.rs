@@ -1,4 +1,89 @@
 use bevy::prelude::*;
 
-#[derive(Component)]
-pub struct PlacedTile;
+use crate::{
+    board::{
+        tetromino_data::TetrominoKind,
+        tile_assets::{GarbageTileImage, TileImages, get_tile_image},
+    },
+    tiles::Tile,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PlacedTileKind {
+    Tetromino(TetrominoKind),
+    Garbage,
+}
+
+#[derive(Component, Copy, Clone, Debug)]
+pub struct PlacedTile {
+    pub kind: PlacedTileKind,
+    /// Which placed piece the tile came from, if any. Counts up per board.
+    pub piece: Option<u32>,
+}
+
+impl PlacedTile {
+    /// Whether two neighbouring tiles stick together under sticky gravity. Tiles from the same
+    /// piece stick, and tiles that weren't placed as a piece stick to others of their kind.
+    pub fn is_connected(&self, other: &PlacedTile) -> bool {
+        match (self.piece, other.piece) {
+            (Some(piece), Some(other_piece)) => piece == other_piece,
+            (None, None) => self.kind == other.kind,
+            _ => false,
+        }
+    }
+}
+
+pub fn spawn_placed_tile(
+    commands: &mut Commands,
+    board_entity: Entity,
+    pos: IVec2,
+    placed_tile: PlacedTile,
+    tile_images: &Res<TileImages>,
+    garbage_tile_image: &Res<GarbageTileImage>,
+) {
+    let image = match placed_tile.kind {
+        PlacedTileKind::Tetromino(kind) => get_tile_image(&tile_images.0, kind),
+        PlacedTileKind::Garbage => garbage_tile_image.0.clone(),
+    };
+
+    commands.spawn((
+        Name::new("PlacedTile"),
+        Tile {
+            pos: pos.as_vec2(),
+            tilemap: board_entity,
+        },
+        placed_tile,
+        ChildOf(board_entity),
+        Sprite::from_image(image),
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile(kind: PlacedTileKind, piece: Option<u32>) -> PlacedTile {
+        PlacedTile { kind, piece }
+    }
+
+    #[test]
+    fn tiles_from_the_same_piece_connect() {
+        let t = PlacedTileKind::Tetromino(TetrominoKind::T);
+        let s = PlacedTileKind::Tetromino(TetrominoKind::S);
+        assert!(tile(t, Some(1)).is_connected(&tile(t, Some(1))));
+        assert!(!tile(t, Some(1)).is_connected(&tile(t, Some(2))));
+        assert!(!tile(t, Some(1)).is_connected(&tile(s, Some(2))));
+    }
+
+    #[test]
+    fn editor_tiles_connect_to_their_kind() {
+        let t = PlacedTileKind::Tetromino(TetrominoKind::T);
+        let s = PlacedTileKind::Tetromino(TetrominoKind::S);
+        let garbage = PlacedTileKind::Garbage;
+        assert!(tile(t, None).is_connected(&tile(t, None)));
+        assert!(tile(garbage, None).is_connected(&tile(garbage, None)));
+        assert!(!tile(t, None).is_connected(&tile(s, None)));
+        assert!(!tile(t, None).is_connected(&tile(garbage, None)));
+        assert!(!tile(t, None).is_connected(&tile(t, Some(1))));
+    }
+}
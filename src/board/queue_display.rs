@@ -2,11 +2,13 @@ use bevy::prelude::*;
 
 use crate::{
     board::{
-        BoardUpdateSystems, TetrominoQueue, TetrominoQueueChanged,
+        Board, BoardUpdateSystems, TetrominoQueue, TetrominoQueueChanged,
+        board_config::{BoardConfig, QueueLayout},
+        editor::BoardEditor,
         tetromino_data::{get_tetromino_display_offset, get_tetromino_shape},
-        tile_assets::TileImages,
+        tile_assets::{TileImages, get_tile_image},
     },
-    tiles::{Tile, TileUpdateSystems},
+    tiles::{Tile, TileUpdateSystems, Tilemap},
 };
 
 pub struct QueueDisplayPlugin;
@@ -26,6 +28,14 @@ impl Plugin for QueueDisplayPlugin {
 pub struct QueueDisplay {
     pub board: Entity,
     pub length: u32,
+    pub layout: QueueLayout,
+}
+
+pub fn get_queue_display_size(length: u32, layout: QueueLayout) -> UVec2 {
+    match layout {
+        QueueLayout::Vertical => uvec2(4, length * 4),
+        QueueLayout::Horizontal => uvec2(length * 4, 4),
+    }
 }
 
 impl QueueDisplay {
@@ -33,26 +43,29 @@ impl QueueDisplay {
         &mut self,
         commands: &mut Commands,
         self_entity: Entity,
-        queue: TetrominoQueue,
+        queue: &TetrominoQueue,
         tile_images: &Res<TileImages>,
         tiles: Query<(Entity, &Tile), With<QueueDisplayTile>>,
     ) {
         QueueDisplay::clear_display(commands, self_entity, tiles);
 
         for (i, kind) in queue.iter().take(self.length as usize).enumerate() {
+            let slot_offset = match self.layout {
+                QueueLayout::Vertical => ivec2(0, 4 * (self.length - 1 - i as u32) as i32),
+                QueueLayout::Horizontal => ivec2(4 * i as i32, 0),
+            };
             for offset in get_tetromino_shape(*kind, 0).iter() {
                 let display_offset = get_tetromino_display_offset(*kind, 0, uvec2(4, 4));
 
                 commands.spawn((
                     Name::new("QueueDisplayTile"),
                     Tile {
-                        pos: (offset + ivec2(0, 4 * (self.length - 1 - i as u32) as i32)).as_vec2()
-                            + display_offset,
+                        pos: (offset + slot_offset).as_vec2() + display_offset,
                         tilemap: self_entity,
                     },
                     QueueDisplayTile,
                     ChildOf(self_entity),
-                    Sprite::from_image(tile_images.0[kind].clone()),
+                    Sprite::from_image(get_tile_image(&tile_images.0, *kind)),
                 ));
             }
         }
@@ -74,27 +87,53 @@ impl QueueDisplay {
 #[derive(Component)]
 pub struct QueueDisplayTile;
 
+/// Redraws a display when its board's queue changes, and resizes it when the preview settings
+/// change.
 fn update_queue_displays(
     mut commands: Commands,
-    mut displays: Query<(Entity, &mut QueueDisplay)>,
+    mut displays: Query<(Entity, &mut QueueDisplay, &mut Tilemap, &mut Mesh2d)>,
+    boards: Query<(&Board, &BoardConfig, Option<&BoardEditor>)>,
     mut queue_changed_messages: MessageReader<TetrominoQueueChanged>,
     tiles: Query<(Entity, &Tile), With<QueueDisplayTile>>,
     tile_images: Res<TileImages>,
+    mut meshes: ResMut<Assets<Mesh>>,
 ) {
     let messages: Vec<_> = queue_changed_messages.read().collect();
 
-    for (display_entity, mut display) in displays.iter_mut() {
-        for message in messages.iter().rev() {
-            if display.board == message.board {
-                display.update_display(
-                    &mut commands,
-                    display_entity,
-                    message.new_queue.clone(),
-                    &tile_images,
-                    tiles,
-                );
-                break;
-            }
+    for (display_entity, mut display, mut tilemap, mut mesh) in displays.iter_mut() {
+        let Ok((board, board_config, editor)) = boards.get(display.board) else {
+            bevy::log::error_once!("Failed to get board in update_queue_displays");
+            continue;
+        };
+
+        let resized = display.length != board_config.queue_display_length
+            || display.layout != board_config.queue_layout;
+        if resized {
+            display.length = board_config.queue_display_length;
+            display.layout = board_config.queue_layout;
+            tilemap.size = get_queue_display_size(display.length, display.layout);
+            mesh.0 = meshes.add(Rectangle::from_size(
+                (tilemap.size * tilemap.tile_size).as_vec2(),
+            ));
+        }
+
+        let message = messages
+            .iter()
+            .rev()
+            .find(|message| message.board == display.board);
+        if message.is_none() && !resized {
+            continue;
+        }
+
+        if editor.is_none() && board.placed_pieces < board_config.queue_hidden_pieces {
+            QueueDisplay::clear_display(&mut commands, display_entity, tiles);
+            continue;
         }
+        let queue = match (message, editor) {
+            (Some(message), _) => &message.new_queue,
+            (None, Some(editor)) => &editor.queue,
+            (None, None) => &board.queue,
+        };
+        display.update_display(&mut commands, display_entity, queue, &tile_images, tiles);
     }
 }
@@ -0,0 +1,335 @@
+use std::{collections::BTreeMap, fs, io, path::PathBuf};
+
+use bevy::{audio::Volume, platform::collections::HashMap, prelude::*};
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter};
+
+use crate::board::{
+    GameOver, GameOverReason, LinesCleared, LinesCollapsed, MoveKind, TetrominoMoved,
+    TetrominoPlaced,
+    spin::Spin,
+    stats::{BoardStats, StatsSystems},
+};
+
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SoundSettings::load())
+            .add_systems(Startup, load_sounds)
+            .add_systems(FixedUpdate, play_board_sounds.after(StatsSystems));
+    }
+}
+
+/// A sample in a sound pack, loaded from `sounds/<pack>/<name>.wav`.
+#[derive(EnumIter, Display, Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[strum(serialize_all = "snake_case")]
+pub enum SoundKind {
+    Shift,
+    Rotate,
+    RotateFailed,
+    HardDrop,
+    Lock,
+    Single,
+    Double,
+    Triple,
+    Tetris,
+    Collapse,
+    TSpin,
+    BackToBack,
+    PerfectClear,
+    /// Warns that garbage is about to rise. Nothing sends garbage yet, so it never plays.
+    IncomingGarbage,
+    TopOut,
+}
+
+impl SoundKind {
+    pub fn category(&self) -> SoundCategory {
+        match self {
+            SoundKind::Shift | SoundKind::HardDrop => SoundCategory::Movement,
+            SoundKind::Rotate | SoundKind::RotateFailed => SoundCategory::Rotation,
+            SoundKind::Lock => SoundCategory::Lock,
+            SoundKind::Single
+            | SoundKind::Double
+            | SoundKind::Triple
+            | SoundKind::Tetris
+            | SoundKind::Collapse => SoundCategory::LineClear,
+            SoundKind::TSpin | SoundKind::BackToBack | SoundKind::PerfectClear => {
+                SoundCategory::Bonus
+            }
+            SoundKind::IncomingGarbage | SoundKind::TopOut => SoundCategory::Alert,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SoundCategory {
+    Movement,
+    Rotation,
+    Lock,
+    LineClear,
+    Bonus,
+    Alert,
+    Music,
+}
+
+/// What happened on a board, reduced to what decides which sounds play.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SoundEvent {
+    Moved(MoveKind),
+    Placed(Spin),
+    LinesCleared {
+        count: u32,
+        back_to_back: bool,
+        perfect_clear: bool,
+    },
+    Collapsed,
+    ToppedOut,
+}
+
+pub fn get_sounds(event: SoundEvent) -> Vec<SoundKind> {
+    match event {
+        SoundEvent::Moved(MoveKind::Shift) => vec![SoundKind::Shift],
+        SoundEvent::Moved(MoveKind::Rotate) => vec![SoundKind::Rotate],
+        SoundEvent::Moved(MoveKind::RotateFailed) => vec![SoundKind::RotateFailed],
+        SoundEvent::Moved(MoveKind::HardDrop | MoveKind::SonicDrop) => vec![SoundKind::HardDrop],
+        SoundEvent::Placed(Spin::None) => vec![SoundKind::Lock],
+        SoundEvent::Placed(_) => vec![SoundKind::Lock, SoundKind::TSpin],
+        SoundEvent::LinesCleared {
+            count,
+            back_to_back,
+            perfect_clear,
+        } => {
+            let mut sounds = match count {
+                0 => vec![],
+                1 => vec![SoundKind::Single],
+                2 => vec![SoundKind::Double],
+                3 => vec![SoundKind::Triple],
+                _ => vec![SoundKind::Tetris],
+            };
+            if back_to_back {
+                sounds.push(SoundKind::BackToBack);
+            }
+            if perfect_clear {
+                sounds.push(SoundKind::PerfectClear);
+            }
+            sounds
+        }
+        SoundEvent::Collapsed => vec![SoundKind::Collapse],
+        SoundEvent::ToppedOut => vec![SoundKind::TopOut],
+    }
+}
+
+/// Sound pack and volumes, loaded from `sound.ron` in the user's config directory.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SoundSettings {
+    /// Folder in `assets/sounds` to load samples from.
+    pub pack: String,
+    pub master_volume: f32,
+    /// Categories left out play at full volume.
+    pub volumes: BTreeMap<SoundCategory, f32>,
+}
+
+impl Default for SoundSettings {
+    fn default() -> Self {
+        Self {
+            pack: "default".to_string(),
+            master_volume: 0.5,
+            volumes: BTreeMap::new(),
+        }
+    }
+}
+
+impl SoundSettings {
+    pub fn volume(&self, category: SoundCategory) -> f32 {
+        let volume = self.volumes.get(&category).copied().unwrap_or(1.0);
+        (self.master_volume * volume).max(0.0)
+    }
+
+    fn load() -> Self {
+        let Some(path) = get_sound_settings_path() else {
+            return Self::default();
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(data) => ron::from_str(&data).unwrap_or_else(|error| {
+                error!("Failed to parse {}: {error}", path.display());
+                Self::default()
+            }),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(error) => {
+                error!("Failed to read {}: {error}", path.display());
+                Self::default()
+            }
+        }
+    }
+}
+
+fn get_sound_settings_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("tetrominoes").join("sound.ron"))
+}
+
+#[derive(Resource, Default)]
+pub struct SoundAssets(pub HashMap<SoundKind, Handle<AudioSource>>);
+
+fn load_sounds(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<SoundSettings>,
+) {
+    let mut sounds = SoundAssets::default();
+    for kind in SoundKind::iter() {
+        let sound = asset_server.load(format!("sounds/{}/{kind}.wav", settings.pack));
+        sounds.0.insert(kind, sound);
+    }
+    commands.insert_resource(sounds);
+}
+
+// ========== Systems ==========
+
+/// Each sound plays at most once a tick, however many boards trigger it.
+fn play_board_sounds(
+    mut commands: Commands,
+    sound_assets: Res<SoundAssets>,
+    settings: Res<SoundSettings>,
+    boards: Query<&BoardStats>,
+    mut moved_messages: MessageReader<TetrominoMoved>,
+    mut placed_messages: MessageReader<TetrominoPlaced>,
+    mut lines_cleared_messages: MessageReader<LinesCleared>,
+    mut collapsed_messages: MessageReader<LinesCollapsed>,
+    mut game_over_messages: MessageReader<GameOver>,
+) {
+    let mut events: Vec<SoundEvent> = moved_messages
+        .read()
+        .filter(|message| boards.contains(message.board))
+        .map(|message| SoundEvent::Moved(message.kind))
+        .collect();
+    events.extend(
+        placed_messages
+            .read()
+            .map(|message| SoundEvent::Placed(message.spin)),
+    );
+    events.extend(lines_cleared_messages.read().map(|message| {
+        SoundEvent::LinesCleared {
+            count: message.count,
+            back_to_back: boards
+                .get(message.board)
+                .is_ok_and(|stats| stats.back_to_back_bonus),
+            perfect_clear: message.perfect_clear,
+        }
+    }));
+    events.extend(collapsed_messages.read().map(|_| SoundEvent::Collapsed));
+    events.extend(
+        game_over_messages
+            .read()
+            .filter(|message| message.reason == GameOverReason::ToppedOut)
+            .map(|_| SoundEvent::ToppedOut),
+    );
+
+    let mut sounds: Vec<SoundKind> = events.into_iter().flat_map(get_sounds).collect();
+    sounds.sort();
+    sounds.dedup();
+
+    for kind in sounds {
+        let volume = settings.volume(kind.category());
+        if volume <= 0.0 {
+            continue;
+        }
+        let Some(sound) = sound_assets.0.get(&kind) else {
+            bevy::log::error_once!("Failed to get sound {kind}!");
+            continue;
+        };
+        commands.spawn((
+            AudioPlayer::new(sound.clone()),
+            PlaybackSettings::DESPAWN.with_volume(Volume::Linear(volume)),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cleared(count: u32) -> SoundEvent {
+        SoundEvent::LinesCleared {
+            count,
+            back_to_back: false,
+            perfect_clear: false,
+        }
+    }
+
+    #[test]
+    fn movement_plays_one_sound() {
+        for (kind, sound) in [
+            (MoveKind::Shift, SoundKind::Shift),
+            (MoveKind::Rotate, SoundKind::Rotate),
+            (MoveKind::RotateFailed, SoundKind::RotateFailed),
+            (MoveKind::HardDrop, SoundKind::HardDrop),
+            (MoveKind::SonicDrop, SoundKind::HardDrop),
+        ] {
+            assert_eq!(get_sounds(SoundEvent::Moved(kind)), [sound]);
+        }
+    }
+
+    #[test]
+    fn placing_plays_lock_and_spin() {
+        assert_eq!(
+            get_sounds(SoundEvent::Placed(Spin::None)),
+            [SoundKind::Lock]
+        );
+        for spin in [Spin::Mini, Spin::Full] {
+            assert_eq!(
+                get_sounds(SoundEvent::Placed(spin)),
+                [SoundKind::Lock, SoundKind::TSpin]
+            );
+        }
+    }
+
+    #[test]
+    fn line_clears_play_by_count() {
+        assert!(get_sounds(cleared(0)).is_empty());
+        assert_eq!(get_sounds(cleared(1)), [SoundKind::Single]);
+        assert_eq!(get_sounds(cleared(2)), [SoundKind::Double]);
+        assert_eq!(get_sounds(cleared(3)), [SoundKind::Triple]);
+        assert_eq!(get_sounds(cleared(4)), [SoundKind::Tetris]);
+        assert_eq!(get_sounds(cleared(5)), [SoundKind::Tetris]);
+        assert_eq!(get_sounds(SoundEvent::Collapsed), [SoundKind::Collapse]);
+    }
+
+    #[test]
+    fn bonuses_stack() {
+        let events = [
+            SoundEvent::Placed(Spin::Full),
+            SoundEvent::LinesCleared {
+                count: 2,
+                back_to_back: true,
+                perfect_clear: true,
+            },
+        ];
+        let sounds: Vec<SoundKind> = events.into_iter().flat_map(get_sounds).collect();
+        assert_eq!(
+            sounds,
+            [
+                SoundKind::Lock,
+                SoundKind::TSpin,
+                SoundKind::Double,
+                SoundKind::BackToBack,
+                SoundKind::PerfectClear,
+            ]
+        );
+    }
+
+    #[test]
+    fn topping_out_plays_alert() {
+        assert_eq!(get_sounds(SoundEvent::ToppedOut), [SoundKind::TopOut]);
+        assert_eq!(SoundKind::TopOut.category(), SoundCategory::Alert);
+    }
+
+    #[test]
+    fn incoming_garbage_is_an_alert() {
+        assert_eq!(SoundKind::IncomingGarbage.to_string(), "incoming_garbage");
+        assert_eq!(SoundKind::IncomingGarbage.category(), SoundCategory::Alert);
+    }
+}
@@ -0,0 +1,44 @@
+use bevy::prelude::*;
+
+use crate::board::tetromino_data::{TetrominoRotation, rotate};
+
+/// The wall kick test that upgrades a mini T-spin to a full one, such as in a T-spin triple.
+const UPGRADING_KICK_INDEX: usize = 4;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Spin {
+    #[default]
+    None,
+    Mini,
+    Full,
+}
+
+/// Checks for a T-spin with the three corner rule. `last_kick` is the wall kick used by the last
+/// rotation, if the piece hasn't moved since, and `is_blocked` takes an offset from the T's centre.
+pub fn get_t_spin(
+    rotation: TetrominoRotation,
+    last_kick: Option<usize>,
+    is_blocked: impl Fn(IVec2) -> bool,
+) -> Spin {
+    let Some(last_kick) = last_kick else {
+        return Spin::None;
+    };
+
+    let is_corner_blocked = |corner: IVec2| is_blocked(rotate(corner, rotation));
+    let front_corners = [ivec2(-1, 1), ivec2(1, 1)]
+        .into_iter()
+        .filter(|corner| is_corner_blocked(*corner))
+        .count();
+    let back_corners = [ivec2(-1, -1), ivec2(1, -1)]
+        .into_iter()
+        .filter(|corner| is_corner_blocked(*corner))
+        .count();
+
+    if front_corners + back_corners < 3 {
+        Spin::None
+    } else if front_corners == 2 || last_kick == UPGRADING_KICK_INDEX {
+        Spin::Full
+    } else {
+        Spin::Mini
+    }
+}
@@ -0,0 +1,391 @@
+use std::time::Duration;
+
+use bevy::{platform::collections::HashMap, prelude::*};
+use leafwing_input_manager::prelude::ActionState;
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter};
+
+use crate::{
+    board::{
+        Board, BoardUpdateSystems, GameEnded, GameOver, GameOverReason, LinesCleared,
+        TetrominoPlaced, editor::BoardEditor, game_mode::ModeProgress,
+        line_clear::LineClearSystems, spin::Spin,
+    },
+    input::Action,
+};
+
+const COMBO_ATTACK: [u32; 12] = [0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5];
+const PERFECT_CLEAR_ATTACK: u32 = 10;
+
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (
+                (update_board_stats, apply_placement_stats)
+                    .chain()
+                    .in_set(StatsSystems),
+                spawn_game_summaries.in_set(StatsDisplaySystems),
+            ),
+        )
+        .configure_sets(
+            FixedUpdate,
+            (
+                StatsSystems
+                    .after(BoardUpdateSystems)
+                    .after(LineClearSystems),
+                StatsDisplaySystems,
+            )
+                .chain(),
+        );
+    }
+}
+
+#[derive(SystemSet, Hash, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatsSystems;
+
+#[derive(SystemSet, Hash, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatsDisplaySystems;
+
+#[derive(EnumIter, Display, Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum ClearKind {
+    Single,
+    Double,
+    Triple,
+    Tetris,
+    #[strum(serialize = "T-Spin Mini")]
+    TSpinMini,
+    #[strum(serialize = "T-Spin Mini Single")]
+    TSpinMiniSingle,
+    #[strum(serialize = "T-Spin Mini Double")]
+    TSpinMiniDouble,
+    #[strum(serialize = "T-Spin")]
+    TSpin,
+    #[strum(serialize = "T-Spin Single")]
+    TSpinSingle,
+    #[strum(serialize = "T-Spin Double")]
+    TSpinDouble,
+    #[strum(serialize = "T-Spin Triple")]
+    TSpinTriple,
+}
+
+impl ClearKind {
+    pub fn new(count: u32, spin: Spin) -> Option<Self> {
+        use ClearKind::*;
+        Some(match (spin, count) {
+            (Spin::None, 0) => return None,
+            (Spin::None, 1) => Single,
+            (Spin::None, 2) => Double,
+            (Spin::None, 3) => Triple,
+            (Spin::None, _) => Tetris,
+            (Spin::Mini, 0) => TSpinMini,
+            (Spin::Mini, 1) => TSpinMiniSingle,
+            (Spin::Mini, _) => TSpinMiniDouble,
+            (Spin::Full, 0) => TSpin,
+            (Spin::Full, 1) => TSpinSingle,
+            (Spin::Full, 2) => TSpinDouble,
+            (Spin::Full, _) => TSpinTriple,
+        })
+    }
+
+    /// Clears that keep a back to back chain going.
+    pub fn is_difficult(self) -> bool {
+        !matches!(
+            self,
+            ClearKind::Single | ClearKind::Double | ClearKind::Triple
+        )
+    }
+}
+
+/// Lines of garbage a clear sends, following the guideline attack table.
+pub fn get_attack(
+    count: u32,
+    spin: Spin,
+    back_to_back: bool,
+    combo: u32,
+    perfect_clear: bool,
+) -> u32 {
+    let base = match (spin, count) {
+        (_, 0) => 0,
+        (Spin::Full, count) => count * 2,
+        (Spin::None, 4) => 4,
+        (_, count) => count - 1,
+    };
+    let combo_attack = COMBO_ATTACK[(combo as usize).min(COMBO_ATTACK.len() - 1)];
+    let perfect_clear_attack = if perfect_clear {
+        PERFECT_CLEAR_ATTACK
+    } else {
+        0
+    };
+
+    base + back_to_back as u32 + combo_attack + perfect_clear_attack
+}
+
+#[derive(Component, Default)]
+pub struct BoardStats {
+    pub time: Duration,
+    pub pieces: u32,
+    pub inputs: u32,
+    pub lines: u32,
+    pub attack: u32,
+    pub combo: u32,
+    pub back_to_back: bool,
+    /// Whether the last line clear continued a back to back chain.
+    pub back_to_back_bonus: bool,
+    pub clears: HashMap<ClearKind, u32>,
+    pub perfect_clears: u32,
+}
+
+impl BoardStats {
+    pub fn pieces_per_second(&self) -> f32 {
+        self.pieces as f32 / self.time.as_secs_f32().max(f32::EPSILON)
+    }
+
+    pub fn attack_per_minute(&self) -> f32 {
+        self.attack as f32 * 60.0 / self.time.as_secs_f32().max(f32::EPSILON)
+    }
+
+    pub fn keys_per_piece(&self) -> f32 {
+        self.inputs as f32 / self.pieces.max(1) as f32
+    }
+
+    fn record_placement(&mut self, count: u32, spin: Spin, perfect_clear: bool) {
+        self.pieces += 1;
+
+        let kind = ClearKind::new(count, spin);
+        if let Some(kind) = kind {
+            *self.clears.entry(kind).or_default() += 1;
+        }
+        let Some(kind) = kind.filter(|_| count > 0) else {
+            self.combo = 0;
+            return;
+        };
+
+        let back_to_back = self.back_to_back && kind.is_difficult();
+        self.back_to_back_bonus = back_to_back;
+        self.attack += get_attack(count, spin, back_to_back, self.combo, perfect_clear);
+        self.back_to_back = kind.is_difficult();
+        self.combo += 1;
+        self.lines += count;
+        if perfect_clear {
+            self.perfect_clears += 1;
+        }
+    }
+
+    /// Lines cleared by tiles falling after an earlier clear. They keep the combo going, but
+    /// don't count as a placement or touch back to back.
+    fn record_chain_clear(&mut self, count: u32, perfect_clear: bool) {
+        if let Some(kind) = ClearKind::new(count, Spin::None) {
+            *self.clears.entry(kind).or_default() += 1;
+        }
+        self.back_to_back_bonus = false;
+        self.attack += get_attack(count, Spin::None, false, self.combo, perfect_clear);
+        self.combo += 1;
+        self.lines += count;
+        if perfect_clear {
+            self.perfect_clears += 1;
+        }
+    }
+
+    fn summary(&self) -> String {
+        let mut summary = format!(
+            "Time {}\nPieces {}  PPS {:.2}\nKPP {:.2}  APM {:.1}\nLines {}  Attack {}",
+            format_time(self.time),
+            self.pieces,
+            self.pieces_per_second(),
+            self.keys_per_piece(),
+            self.attack_per_minute(),
+            self.lines,
+            self.attack,
+        );
+        for kind in ClearKind::iter() {
+            if let Some(count) = self.clears.get(&kind) {
+                summary.push_str(&format!("\n{kind} {count}"));
+            }
+        }
+        if self.perfect_clears > 0 {
+            summary.push_str(&format!("\nPerfect Clear {}", self.perfect_clears));
+        }
+        summary
+    }
+}
+
+pub fn format_time(time: Duration) -> String {
+    let centiseconds = time.as_millis() / 10;
+    format!(
+        "{}:{:02}.{:02}",
+        centiseconds / 6000,
+        centiseconds / 100 % 60,
+        centiseconds % 100
+    )
+}
+
+#[derive(Component)]
+pub struct GameSummary;
+
+// ========== Systems ==========
+
+fn update_board_stats(
+    mut boards: Query<
+        (&mut BoardStats, &ActionState<Action>),
+        (Without<GameEnded>, Without<BoardEditor>),
+    >,
+    time: Res<Time>,
+) {
+    for (mut stats, action_state) in boards.iter_mut() {
+        stats.time += time.delta();
+        stats.inputs += action_state.get_just_pressed().len() as u32;
+    }
+}
+
+fn apply_placement_stats(
+    mut boards: Query<&mut BoardStats>,
+    mut placed_messages: MessageReader<TetrominoPlaced>,
+    mut lines_cleared_messages: MessageReader<LinesCleared>,
+) {
+    let lines_cleared: Vec<_> = lines_cleared_messages.read().collect();
+
+    for message in placed_messages.read() {
+        let Ok(mut stats) = boards.get_mut(message.board) else {
+            bevy::log::error_once!("Failed to get board stats when placing tetromino!");
+            continue;
+        };
+
+        match lines_cleared
+            .iter()
+            .find(|cleared| cleared.board == message.board && cleared.chain == 0)
+        {
+            Some(cleared) => {
+                stats.record_placement(cleared.count, cleared.spin, cleared.perfect_clear)
+            }
+            None => stats.record_placement(0, message.spin, false),
+        }
+    }
+
+    for cleared in lines_cleared.iter().filter(|cleared| cleared.chain > 0) {
+        let Ok(mut stats) = boards.get_mut(cleared.board) else {
+            bevy::log::error_once!("Failed to get board stats when chaining a line clear!");
+            continue;
+        };
+        stats.record_chain_clear(cleared.count, cleared.perfect_clear);
+    }
+}
+
+fn spawn_game_summaries(
+    mut commands: Commands,
+    boards: Query<(&BoardStats, &ModeProgress, &GlobalTransform), With<Board>>,
+    mut game_over_messages: MessageReader<GameOver>,
+) {
+    for message in game_over_messages.read() {
+        let Ok((stats, progress, transform)) = boards.get(message.board) else {
+            bevy::log::error_once!("Failed to get board when showing game summary!");
+            continue;
+        };
+
+        let title = match message.reason {
+            GameOverReason::ToppedOut => "Game Over",
+            GameOverReason::Completed => "Complete",
+        };
+        commands.spawn((
+            Name::new("GameSummary"),
+            GameSummary,
+            Text2d::new(format!(
+                "{title}\n\nScore {}\n{}",
+                progress.score,
+                stats.summary()
+            )),
+            TextLayout::new_with_justify(Justify::Center),
+            Transform::from_translation(transform.translation().with_z(10.0)),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clear_kinds_tell_mini_and_full_spins_apart() {
+        assert_eq!(ClearKind::new(0, Spin::None), None);
+        assert_eq!(ClearKind::new(2, Spin::None), Some(ClearKind::Double));
+        assert_eq!(ClearKind::new(4, Spin::None), Some(ClearKind::Tetris));
+        assert_eq!(ClearKind::new(0, Spin::Mini), Some(ClearKind::TSpinMini));
+        assert_eq!(
+            ClearKind::new(1, Spin::Mini),
+            Some(ClearKind::TSpinMiniSingle)
+        );
+        assert_eq!(ClearKind::new(0, Spin::Full), Some(ClearKind::TSpin));
+        assert_eq!(ClearKind::new(1, Spin::Full), Some(ClearKind::TSpinSingle));
+        assert_eq!(ClearKind::new(3, Spin::Full), Some(ClearKind::TSpinTriple));
+    }
+
+    #[test]
+    fn attack_follows_the_guideline_table() {
+        assert_eq!(get_attack(1, Spin::None, false, 0, false), 0);
+        assert_eq!(get_attack(3, Spin::None, false, 0, false), 2);
+        assert_eq!(get_attack(4, Spin::None, false, 0, false), 4);
+        assert_eq!(get_attack(1, Spin::Mini, false, 0, false), 0);
+        assert_eq!(get_attack(2, Spin::Mini, false, 0, false), 1);
+        assert_eq!(get_attack(2, Spin::Full, false, 0, false), 4);
+        assert_eq!(get_attack(4, Spin::None, true, 0, false), 5);
+        assert_eq!(get_attack(1, Spin::None, false, 4, false), 2);
+        assert_eq!(get_attack(1, Spin::None, false, 100, false), 5);
+        assert_eq!(get_attack(4, Spin::None, false, 0, true), 14);
+    }
+
+    #[test]
+    fn combos_build_and_reset() {
+        let mut stats = BoardStats::default();
+        for _ in 0..3 {
+            stats.record_placement(1, Spin::None, false);
+        }
+        assert_eq!(stats.combo, 3);
+        assert_eq!(stats.attack, 1);
+        assert_eq!(stats.clears[&ClearKind::Single], 3);
+
+        stats.record_placement(0, Spin::None, false);
+        assert_eq!(stats.combo, 0);
+        assert_eq!(stats.pieces, 4);
+        assert_eq!(stats.lines, 3);
+    }
+
+    #[test]
+    fn back_to_back_adds_a_bonus_until_broken() {
+        let mut stats = BoardStats::default();
+        stats.record_placement(4, Spin::None, false);
+        assert!(stats.back_to_back);
+        assert!(!stats.back_to_back_bonus);
+        assert_eq!(stats.attack, 4);
+
+        stats.record_placement(2, Spin::Full, false);
+        assert!(stats.back_to_back_bonus);
+        assert_eq!(stats.attack, 9);
+
+        // A spin without lines breaks the combo but not the chain
+        stats.record_placement(0, Spin::Mini, false);
+        assert!(stats.back_to_back);
+        assert_eq!(stats.combo, 0);
+        assert_eq!(stats.clears[&ClearKind::TSpinMini], 1);
+
+        stats.record_placement(1, Spin::None, false);
+        assert!(!stats.back_to_back);
+        assert!(!stats.back_to_back_bonus);
+        assert_eq!(stats.attack, 9);
+
+        stats.record_placement(4, Spin::None, false);
+        assert!(!stats.back_to_back_bonus);
+        assert_eq!(stats.attack, 13);
+    }
+
+    #[test]
+    fn perfect_clears_are_counted() {
+        let mut stats = BoardStats::default();
+        stats.record_placement(4, Spin::None, true);
+        stats.record_placement(2, Spin::None, false);
+        assert_eq!(stats.perfect_clears, 1);
+        assert_eq!(stats.attack, 15);
+        assert_eq!(stats.clears[&ClearKind::Tetris], 1);
+    }
+}
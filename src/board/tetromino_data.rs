@@ -1,8 +1,24 @@
 use bevy::prelude::*;
 use rand::{Rng, seq::IndexedRandom};
-use strum_macros::{EnumCount, EnumIter};
-
-#[derive(EnumIter, EnumCount, Copy, Clone, Debug, Hash, PartialEq, Eq)]
+use serde::Deserialize;
+use strum_macros::{EnumCount, EnumIter, EnumString, IntoStaticStr};
+
+#[derive(
+    EnumIter,
+    EnumCount,
+    EnumString,
+    IntoStaticStr,
+    Deserialize,
+    Copy,
+    Clone,
+    Debug,
+    Hash,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+)]
+#[strum(ascii_case_insensitive)]
 pub enum TetrominoKind {
     I,
     J,
@@ -28,8 +44,8 @@ const fn shape(kind: TetrominoKind) -> TetrominoShape {
     }
 }
 
-fn rotate(point: IVec2, rotation: TetrominoRotation) -> IVec2 {
-    match rotation % 4 {
+pub fn rotate(point: IVec2, rotation: TetrominoRotation) -> IVec2 {
+    match rotation.rem_euclid(4) {
         0 => ivec2(point.x, point.y),
         1 => ivec2(point.y, -point.x),
         2 => ivec2(-point.x, -point.y),
@@ -51,21 +67,21 @@ fn offsets(kind: TetrominoKind, rotation: TetrominoRotation) -> Vec<IVec2> {
     const L: TetrominoRotation = 3;
 
     let offsets = match kind {
-        TetrominoKind::O => match rotation % 4 {
+        TetrominoKind::O => match rotation.rem_euclid(4) {
             0 => vec![(0, 0)],
             R => vec![(0, -1)],
             2 => vec![(-1, -1)],
             L => vec![(-1, 0)],
             _ => unreachable!(),
         },
-        TetrominoKind::I => match rotation % 4 {
+        TetrominoKind::I => match rotation.rem_euclid(4) {
             0 => vec![(0, 0), (-1, 0), (2, 0), (-1, 0), (2, 0)],
             R => vec![(-1, 0), (0, 0), (0, 0), (0, 1), (0, -2)],
             2 => vec![(-1, 1), (1, 1), (-2, 1), (1, 0), (-2, 0)],
             L => vec![(0, 1), (0, 1), (0, 1), (0, -1), (0, 2)],
             _ => unreachable!(),
         },
-        _ => match rotation % 4 {
+        _ => match rotation.rem_euclid(4) {
             0 => vec![(0, 0), (0, 0), (0, 0), (0, 0), (0, 0)],
             R => vec![(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
             2 => vec![(0, 0), (0, 0), (0, 0), (0, 0), (0, 0)],
@@ -99,18 +115,6 @@ pub fn get_tetromino_wall_kicks(
         .collect()
 }
 
-pub const fn get_tetromino_color(kind: TetrominoKind) -> &'static str {
-    match kind {
-        TetrominoKind::I => "blue",
-        TetrominoKind::J => "pink",
-        TetrominoKind::L => "orange",
-        TetrominoKind::O => "yellow",
-        TetrominoKind::S => "red",
-        TetrominoKind::T => "purple",
-        TetrominoKind::Z => "green",
-    }
-}
-
 pub fn get_tetromino_start_piece<R: Rng>(mut rng: R) -> TetrominoKind {
     *[
         TetrominoKind::I,
@@ -144,3 +148,21 @@ pub fn get_tetromino_display_offset(
 
     display_size.as_vec2() / 2.0 - size.as_vec2() / 2.0 - bounds.0.as_vec2()
 }
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    #[test]
+    fn negative_rotations_wrap() {
+        for kind in TetrominoKind::iter() {
+            assert_eq!(get_tetromino_shape(kind, -1), get_tetromino_shape(kind, 3));
+            assert_eq!(
+                get_tetromino_wall_kicks(0, -1, kind),
+                get_tetromino_wall_kicks(0, 3, kind)
+            );
+        }
+    }
+}
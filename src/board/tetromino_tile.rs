@@ -6,7 +6,7 @@ use crate::{
         board_config::BoardConfig,
         outline::TetrominoTileOutline,
         tetromino_data::get_tetromino_shape,
-        tile_assets::{TileImages, TileOutlineImages},
+        tile_assets::{TileImages, TileOutlineImages, get_tile_image},
     },
     tiles::{Tile, TileUpdateSystems},
 };
@@ -52,7 +52,7 @@ pub fn spawn_tetromino_tiles(
                 offset_index: index,
             },
             ChildOf(board_entity),
-            Sprite::from_image(tile_images.0[&board.kind].clone()),
+            Sprite::from_image(get_tile_image(&tile_images.0, board.kind)),
         ));
         commands.spawn((
             Name::new("TetrominoTileOutline"),
@@ -65,7 +65,7 @@ pub fn spawn_tetromino_tiles(
             },
             TetrominoTileOutline,
             ChildOf(board_entity),
-            Sprite::from_image(tile_outline_images.0[&board.kind].clone()),
+            Sprite::from_image(get_tile_image(&tile_outline_images.0, board.kind)),
             Transform::from_translation(Vec3::new(0.0, 0.0, 2.0)),
         ));
     }
@@ -110,10 +110,8 @@ fn apply_lock_delay_visuals(
             continue;
         };
 
-        let normal_effect =
-            board.stationary_lock_delay as f32 / board_config.stationary_lock_delay as f32;
-        let stationary_effect = board.lock_delay as f32 / board_config.lock_delay as f32;
+        let lock_effect = board.lock_delay as f32 / board_config.lock_delay.max(1) as f32;
 
-        sprite.color.set_alpha(normal_effect.min(stationary_effect));
+        sprite.color.set_alpha(lock_effect.min(1.0));
     }
 }
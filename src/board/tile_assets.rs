@@ -1,36 +1,397 @@
-use bevy::{platform::collections::HashMap, prelude::*};
+use std::{collections::BTreeMap, fmt, io, marker::PhantomData};
+
+use bevy::{
+    asset::{AssetLoadFailedEvent, AssetLoader, LoadContext, io::Reader},
+    platform::collections::HashMap,
+    prelude::*,
+};
+use serde::{Deserialize, de::DeserializeOwned};
 use strum::IntoEnumIterator;
 
-use crate::board::tetromino_data::{TetrominoKind, get_tetromino_color};
+use crate::board::tetromino_data::TetrominoKind;
+
+const DEFAULT_SKIN: &str = "default";
+const NEXT_SKIN_KEY: KeyCode = KeyCode::F2;
 
 pub struct TileAssets;
 
 impl Plugin for TileAssets {
     fn build(&self, app: &mut App) {
-        app.add_systems(PreStartup, setup); // TODO make this not prestartup. Use a loading state ideally
+        app.init_asset::<SkinManifest>()
+            .init_asset::<SkinList>()
+            .register_asset_loader(RonAssetLoader::<SkinManifest>::default())
+            .register_asset_loader(RonAssetLoader::<SkinList>::default())
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (select_next_skin, apply_skin, apply_skin_fallbacks).chain(),
+            );
     }
 }
 
-#[derive(Resource, Default)]
+#[derive(Resource)]
 pub struct TileImages(pub HashMap<TetrominoKind, Handle<Image>>);
 
-#[derive(Resource, Default)]
+#[derive(Resource)]
 pub struct TileOutlineImages(pub HashMap<TetrominoKind, Handle<Image>>);
 
+#[derive(Resource)]
+pub struct GhostTileImages(pub HashMap<TetrominoKind, Handle<Image>>);
+
+#[derive(Resource)]
+pub struct GarbageTileImage(pub Handle<Image>);
+
+#[derive(Resource)]
+pub struct LineClearImage(pub Handle<Image>);
+
+/// Falls back to a blank texture rather than panicking if `images` has no entry for `kind`.
+pub fn get_tile_image(
+    images: &HashMap<TetrominoKind, Handle<Image>>,
+    kind: TetrominoKind,
+) -> Handle<Image> {
+    images.get(&kind).cloned().unwrap_or_else(|| {
+        bevy::log::error_once!("Failed to get tile image for {kind:?}!");
+        Handle::default()
+    })
+}
+
+/// Lists a skin's textures, relative to its folder in `assets/skins`. Anything left out is taken
+/// from the default skin.
+#[derive(Asset, TypePath, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct SkinManifest {
+    pub minos: BTreeMap<TetrominoKind, String>,
+    pub outlines: BTreeMap<TetrominoKind, String>,
+    pub ghosts: BTreeMap<TetrominoKind, String>,
+    pub garbage: Option<String>,
+    pub line_clear: Option<String>,
+}
+
+/// Skins that can be selected, from `assets/skins/skins.ron`.
+#[derive(Asset, TypePath, Deserialize)]
+#[serde(transparent)]
+pub struct SkinList(pub Vec<String>);
+
+#[derive(Debug)]
+pub enum RonAssetError {
+    Read(io::Error),
+    Parse(ron::error::SpannedError),
+}
+
+impl fmt::Display for RonAssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RonAssetError::Read(error) => write!(f, "failed to read asset: {error}"),
+            RonAssetError::Parse(error) => write!(f, "invalid asset: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for RonAssetError {}
+
+struct RonAssetLoader<A>(PhantomData<fn() -> A>);
+
+impl<A> Default for RonAssetLoader<A> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<A: Asset + DeserializeOwned> AssetLoader for RonAssetLoader<A> {
+    type Asset = A;
+    type Settings = ();
+    type Error = RonAssetError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<A, RonAssetError> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(RonAssetError::Read)?;
+        ron::Options::default()
+            .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+            .from_bytes(&bytes)
+            .map_err(RonAssetError::Parse)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ron"]
+    }
+}
+
+#[derive(Clone)]
+struct SkinTextures {
+    minos: HashMap<TetrominoKind, Handle<Image>>,
+    outlines: HashMap<TetrominoKind, Handle<Image>>,
+    ghosts: HashMap<TetrominoKind, Handle<Image>>,
+    garbage: Handle<Image>,
+    line_clear: Handle<Image>,
+}
+
+/// Placeholders until the default skin's manifest has loaded.
+impl Default for SkinTextures {
+    fn default() -> Self {
+        let placeholders: HashMap<_, _> = TetrominoKind::iter()
+            .map(|kind| (kind, Handle::default()))
+            .collect();
+        Self {
+            minos: placeholders.clone(),
+            outlines: placeholders.clone(),
+            ghosts: placeholders,
+            garbage: Handle::default(),
+            line_clear: Handle::default(),
+        }
+    }
+}
+
+impl SkinTextures {
+    /// Loads the textures `manifest` lists, taking the rest from `fallback`.
+    fn load(
+        asset_server: &AssetServer,
+        skin: &str,
+        manifest: &SkinManifest,
+        fallback: &SkinTextures,
+    ) -> Self {
+        let load = |path: Option<&String>, fallback: &Handle<Image>| {
+            let Some(path) = path else {
+                return fallback.clone();
+            };
+            let handle = asset_server.load(format!("skins/{skin}/{path}"));
+            // Already failed while another skin was selected, so no failure message will come
+            if asset_server.load_state(&handle).is_failed() {
+                return fallback.clone();
+            }
+            handle
+        };
+        let load_kinds =
+            |paths: &BTreeMap<TetrominoKind, String>,
+             fallbacks: &HashMap<TetrominoKind, Handle<Image>>| {
+                TetrominoKind::iter()
+                    .map(|kind| (kind, load(paths.get(&kind), &fallbacks[&kind])))
+                    .collect()
+            };
+
+        Self {
+            minos: load_kinds(&manifest.minos, &fallback.minos),
+            outlines: load_kinds(&manifest.outlines, &fallback.outlines),
+            ghosts: load_kinds(&manifest.ghosts, &fallback.ghosts),
+            garbage: load(manifest.garbage.as_ref(), &fallback.garbage),
+            line_clear: load(manifest.line_clear.as_ref(), &fallback.line_clear),
+        }
+    }
+
+    /// Every texture in a fixed order, so the textures of two skins can be matched up.
+    fn get_slots(&self) -> Vec<Handle<Image>> {
+        let mut slots = Vec::new();
+        for kind in TetrominoKind::iter() {
+            slots.push(self.minos[&kind].clone());
+            slots.push(self.outlines[&kind].clone());
+            slots.push(self.ghosts[&kind].clone());
+        }
+        slots.push(self.garbage.clone());
+        slots.push(self.line_clear.clone());
+        slots
+    }
+
+    fn replace(&mut self, replacements: &HashMap<AssetId<Image>, Handle<Image>>) {
+        let replace = |handle: &mut Handle<Image>| {
+            if let Some(replacement) = replacements.get(&handle.id()) {
+                *handle = replacement.clone();
+            }
+        };
+        self.minos.values_mut().for_each(replace);
+        self.outlines.values_mut().for_each(replace);
+        self.ghosts.values_mut().for_each(replace);
+        replace(&mut self.garbage);
+        replace(&mut self.line_clear);
+    }
+
+    fn insert_resources(&self, commands: &mut Commands) {
+        commands.insert_resource(TileImages(self.minos.clone()));
+        commands.insert_resource(TileOutlineImages(self.outlines.clone()));
+        commands.insert_resource(GhostTileImages(self.ghosts.clone()));
+        commands.insert_resource(GarbageTileImage(self.garbage.clone()));
+        commands.insert_resource(LineClearImage(self.line_clear.clone()));
+    }
+}
+
+/// The selected skin. Its textures replace the previous skin's, including on tiles already
+/// spawned, once its manifest and the default skin's have loaded.
+#[derive(Resource)]
+pub struct Skin {
+    pub name: String,
+    list: Handle<SkinList>,
+    default_manifest: Handle<SkinManifest>,
+    manifest: Handle<SkinManifest>,
+    applied: bool,
+    textures: SkinTextures,
+    /// Default skin textures for the selected skin's textures, in case they fail to load.
+    fallbacks: HashMap<AssetId<Image>, Handle<Image>>,
+}
+
+impl Skin {
+    pub fn is_applied(&self) -> bool {
+        self.applied
+    }
+
+    /// The manifests and, once the skin is applied, its textures.
+    pub fn get_handles(&self) -> Vec<UntypedHandle> {
+        let mut handles = vec![
+            self.list.clone().untyped(),
+            self.default_manifest.clone().untyped(),
+            self.manifest.clone().untyped(),
+        ];
+        if self.applied {
+            handles.extend(self.textures.get_slots().into_iter().map(Handle::untyped));
+        }
+        handles
+    }
+}
+
+fn get_manifest_path(skin: &str) -> String {
+    format!("skins/{skin}/skin.ron")
+}
+
+fn replace_sprite_images(
+    sprites: &mut Query<&mut Sprite>,
+    replacements: &HashMap<AssetId<Image>, Handle<Image>>,
+) {
+    for mut sprite in sprites.iter_mut() {
+        if let Some(replacement) = replacements.get(&sprite.image.id()) {
+            sprite.image = replacement.clone();
+        }
+    }
+}
+
 fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let mut images = TileImages::default();
-    let mut outline_images = TileOutlineImages::default();
+    let default_manifest = asset_server.load(get_manifest_path(DEFAULT_SKIN));
+    let textures = SkinTextures::default();
+    textures.insert_resources(&mut commands);
+
+    commands.insert_resource(Skin {
+        name: DEFAULT_SKIN.to_string(),
+        list: asset_server.load("skins/skins.ron"),
+        manifest: default_manifest.clone(),
+        default_manifest,
+        applied: false,
+        textures,
+        fallbacks: HashMap::default(),
+    });
+}
+
+// ========== Systems ==========
 
-    for kind in TetrominoKind::iter() {
-        let color_str = get_tetromino_color(kind);
+fn select_next_skin(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut skin: ResMut<Skin>,
+    skin_lists: Res<Assets<SkinList>>,
+    asset_server: Res<AssetServer>,
+) {
+    if !keys.just_pressed(NEXT_SKIN_KEY) {
+        return;
+    }
+    let Some(skin_list) = skin_lists.get(&skin.list) else {
+        warn!("The skin list isn't loaded");
+        return;
+    };
+
+    let index = skin_list
+        .0
+        .iter()
+        .position(|name| *name == skin.name)
+        .map_or(0, |index| index + 1);
+    let Some(name) = skin_list.0.get(index).or(skin_list.0.first()).cloned() else {
+        return;
+    };
+
+    info!("Selected skin {name}");
+    skin.manifest = asset_server.load(get_manifest_path(&name));
+    skin.name = name;
+    skin.applied = false;
+}
 
-        let image: Handle<Image> = asset_server.load(format!("tiles/tile_{}.png", color_str));
-        images.0.insert(kind, image);
-        let outline_image: Handle<Image> =
-            asset_server.load(format!("tiles/outline_{}.png", color_str));
-        outline_images.0.insert(kind, outline_image);
+fn apply_skin(
+    mut commands: Commands,
+    mut skin: ResMut<Skin>,
+    manifests: Res<Assets<SkinManifest>>,
+    asset_server: Res<AssetServer>,
+    mut sprites: Query<&mut Sprite>,
+) {
+    if skin.applied {
+        return;
+    }
+    let Some(default_manifest) = manifests.get(&skin.default_manifest) else {
+        if asset_server.load_state(&skin.default_manifest).is_failed() {
+            bevy::log::error_once!("Failed to load the default skin!");
+        }
+        return;
+    };
+
+    let default_textures = SkinTextures::load(
+        &asset_server,
+        DEFAULT_SKIN,
+        default_manifest,
+        &SkinTextures::default(),
+    );
+    let textures = if skin.name == DEFAULT_SKIN {
+        default_textures.clone()
+    } else if let Some(manifest) = manifests.get(&skin.manifest) {
+        SkinTextures::load(&asset_server, &skin.name, manifest, &default_textures)
+    } else if asset_server.load_state(&skin.manifest).is_failed() {
+        error!("Failed to load skin {}, using the default skin", skin.name);
+        default_textures.clone()
+    } else {
+        return;
+    };
+
+    skin.fallbacks = textures
+        .get_slots()
+        .into_iter()
+        .zip(default_textures.get_slots())
+        .filter(|(texture, fallback)| texture != fallback)
+        .map(|(texture, fallback)| (texture.id(), fallback))
+        .collect();
+
+    let replacements = skin
+        .textures
+        .get_slots()
+        .iter()
+        .map(Handle::id)
+        .zip(textures.get_slots())
+        // Plain colored sprites use the placeholder image too
+        .filter(|(previous, _)| *previous != AssetId::default())
+        .collect();
+    replace_sprite_images(&mut sprites, &replacements);
+
+    textures.insert_resources(&mut commands);
+    skin.textures = textures;
+    skin.applied = true;
+}
+
+/// Swaps textures that failed to load for the default skin's.
+fn apply_skin_fallbacks(
+    mut commands: Commands,
+    mut skin: ResMut<Skin>,
+    mut failed_messages: MessageReader<AssetLoadFailedEvent<Image>>,
+    mut sprites: Query<&mut Sprite>,
+) {
+    let mut replacements = HashMap::default();
+    for message in failed_messages.read() {
+        if let Some(fallback) = skin.fallbacks.get(&message.id) {
+            warn!("{}, using the default skin's texture", message.error);
+            replacements.insert(message.id, fallback.clone());
+        }
+    }
+    if replacements.is_empty() {
+        return;
     }
 
-    commands.insert_resource(images);
-    commands.insert_resource(outline_images);
+    replace_sprite_images(&mut sprites, &replacements);
+    skin.textures.replace(&replacements);
+    skin.textures.insert_resources(&mut commands);
 }
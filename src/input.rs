@@ -1,44 +1,260 @@
-use bevy::prelude::*;
+use bevy::{platform::collections::HashSet, prelude::*};
 use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumCount, EnumIter};
+
+pub mod bindings;
+mod rebind_menu;
+
+use crate::input::{
+    bindings::{Bindings, PlayerBindings, apply_player_bindings},
+    rebind_menu::RebindMenuPlugin,
+};
 
 pub struct InputPlugin;
 
 impl Plugin for InputPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(InputManagerPlugin::<Action>::default());
+        app.add_plugins((
+            InputManagerPlugin::<Action>::default(),
+            InputManagerPlugin::<EditorAction>::default(),
+            RebindMenuPlugin,
+        ))
+        .insert_resource(PlayerBindings::load())
+        .add_message::<ClaimGamepad>()
+        .add_systems(
+            Update,
+            (apply_player_bindings, apply_gamepad_claims, assign_gamepads).chain(),
+        );
     }
 }
 
-#[derive(Actionlike, PartialEq, Eq, Hash, Clone, Copy, Debug, Reflect)]
+/// Local player index. Players are given gamepads in order as they connect, or claim one from the
+/// controls menu.
+#[derive(Component, Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Player(pub usize);
+
+#[derive(Component, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PlayerGamepad(pub Entity);
+
+/// Gives a gamepad to a player, taking it from whoever had it.
+#[derive(Message, Copy, Clone, Debug)]
+pub struct ClaimGamepad {
+    pub player: Player,
+    pub gamepad: Entity,
+}
+
+#[derive(
+    Actionlike,
+    EnumIter,
+    EnumCount,
+    Display,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Clone,
+    Copy,
+    Debug,
+    Reflect,
+)]
 pub enum Action {
     ShiftLeft,
     ShiftRight,
     SoftDrop,
     HardDrop,
+    /// Keeps the piece on the floor while held, without locking it.
+    SonicDrop,
+    /// Drops the piece to the floor once per press, without locking it.
+    FirmDrop,
     RotateLeft,
     RotateRight,
     Hold,
 }
 
+#[derive(Actionlike, PartialEq, Eq, Hash, Clone, Copy, Debug, Reflect)]
+pub enum EditorAction {
+    Toggle,
+    Paint,
+    Erase,
+    NextKind,
+    PreviousKind,
+    ToggleHold,
+    PushQueue,
+    PopQueue,
+    ClearQueue,
+    ExportFumen,
+    ImportFumen,
+}
+
+/// Default bindings, until the board's player has their saved bindings applied.
 pub fn get_board_input_map() -> InputMap<Action> {
-    use Action::*;
+    Bindings::default().get_input_map()
+}
+
+pub fn get_editor_input_map() -> InputMap<EditorAction> {
+    use EditorAction::*;
     let mut input_map = InputMap::default();
 
-    input_map.insert(ShiftLeft, KeyCode::KeyA);
-    input_map.insert(ShiftLeft, KeyCode::ArrowLeft);
+    input_map.insert(Toggle, KeyCode::Tab);
 
-    input_map.insert(ShiftRight, KeyCode::KeyD);
-    input_map.insert(ShiftRight, KeyCode::ArrowRight);
+    input_map.insert(Paint, MouseButton::Left);
+    input_map.insert(Erase, MouseButton::Right);
 
-    input_map.insert(SoftDrop, KeyCode::KeyS);
-    input_map.insert(SoftDrop, KeyCode::ArrowDown);
+    input_map.insert(NextKind, KeyCode::Period);
+    input_map.insert(PreviousKind, KeyCode::Comma);
 
-    input_map.insert(HardDrop, KeyCode::Space);
+    input_map.insert(ToggleHold, KeyCode::KeyH);
 
-    input_map.insert(RotateRight, KeyCode::KeyW);
-    input_map.insert(RotateRight, KeyCode::ArrowUp);
+    input_map.insert(PushQueue, KeyCode::Enter);
+    input_map.insert(PopQueue, KeyCode::Backspace);
+    input_map.insert(ClearQueue, KeyCode::Delete);
 
-    input_map.insert(Hold, KeyCode::KeyC);
+    input_map.insert(ExportFumen, KeyCode::KeyF);
+    input_map.insert(ImportFumen, KeyCode::KeyI);
 
     input_map
 }
+
+fn apply_gamepad_claims(
+    mut commands: Commands,
+    mut players: Query<(
+        Entity,
+        &Player,
+        &mut InputMap<Action>,
+        Option<&PlayerGamepad>,
+    )>,
+    mut claim_messages: MessageReader<ClaimGamepad>,
+) {
+    for claim in claim_messages.read() {
+        for (entity, player, mut input_map, gamepad) in players.iter_mut() {
+            if *player == claim.player {
+                info!("Player {} claimed gamepad {}", player.0 + 1, claim.gamepad);
+                input_map.set_gamepad(claim.gamepad);
+                commands.entity(entity).insert(PlayerGamepad(claim.gamepad));
+            } else if gamepad.is_some_and(|gamepad| gamepad.0 == claim.gamepad) {
+                input_map.set_gamepad(Entity::PLACEHOLDER);
+                commands.entity(entity).remove::<PlayerGamepad>();
+            }
+        }
+    }
+}
+
+/// Gives each player without a gamepad the oldest unassigned one, and frees gamepads that were
+/// disconnected.
+fn assign_gamepads(
+    mut commands: Commands,
+    mut players: Query<(
+        Entity,
+        &Player,
+        &mut InputMap<Action>,
+        Option<&PlayerGamepad>,
+    )>,
+    gamepads: Query<Entity, With<Gamepad>>,
+) {
+    let assigned: HashSet<Entity> = players
+        .iter()
+        .filter_map(|(_, _, _, gamepad)| gamepad.map(|gamepad| gamepad.0))
+        .filter(|gamepad| gamepads.contains(*gamepad))
+        .collect();
+    let mut free_gamepads: Vec<Entity> = gamepads
+        .iter()
+        .filter(|gamepad| !assigned.contains(gamepad))
+        .collect();
+    free_gamepads.sort_by_key(|gamepad| gamepad.index());
+    let mut free_gamepads = free_gamepads.into_iter();
+
+    let mut unassigned_players: Vec<_> = players
+        .iter_mut()
+        .filter(|(_, _, _, gamepad)| !gamepad.is_some_and(|gamepad| assigned.contains(&gamepad.0)))
+        .collect();
+    unassigned_players.sort_by_key(|(_, player, _, _)| **player);
+
+    for (entity, player, mut input_map, gamepad) in unassigned_players {
+        if let Some(new_gamepad) = free_gamepads.next() {
+            info!("Assigned gamepad {new_gamepad} to player {}", player.0 + 1);
+            input_map.set_gamepad(new_gamepad);
+            commands.entity(entity).insert(PlayerGamepad(new_gamepad));
+        } else if gamepad.is_some() {
+            info!("Player {} lost their gamepad", player.0 + 1);
+            input_map.set_gamepad(Entity::PLACEHOLDER);
+            commands.entity(entity).remove::<PlayerGamepad>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    fn spawn_player(world: &mut World, index: usize) -> Entity {
+        world
+            .spawn((Player(index), InputMap::<Action>::default()))
+            .id()
+    }
+
+    fn get_gamepad(world: &World, player: Entity) -> Option<Entity> {
+        world.get::<PlayerGamepad>(player).map(|gamepad| gamepad.0)
+    }
+
+    #[test]
+    fn assigns_gamepads_in_player_order() {
+        let mut world = World::new();
+        let second = spawn_player(&mut world, 1);
+        let first = spawn_player(&mut world, 0);
+        let gamepad_a = world.spawn(Gamepad::default()).id();
+
+        world.run_system_once(assign_gamepads).unwrap();
+        assert_eq!(get_gamepad(&world, first), Some(gamepad_a));
+        assert_eq!(get_gamepad(&world, second), None);
+
+        let gamepad_b = world.spawn(Gamepad::default()).id();
+        world.run_system_once(assign_gamepads).unwrap();
+        assert_eq!(get_gamepad(&world, first), Some(gamepad_a));
+        assert_eq!(get_gamepad(&world, second), Some(gamepad_b));
+    }
+
+    #[test]
+    fn reassigns_gamepads_on_disconnect() {
+        let mut world = World::new();
+        let first = spawn_player(&mut world, 0);
+        let second = spawn_player(&mut world, 1);
+        let gamepad_a = world.spawn(Gamepad::default()).id();
+        let gamepad_b = world.spawn(Gamepad::default()).id();
+        world.run_system_once(assign_gamepads).unwrap();
+
+        world.despawn(gamepad_a);
+        world.run_system_once(assign_gamepads).unwrap();
+        assert_eq!(get_gamepad(&world, first), None);
+        assert_eq!(get_gamepad(&world, second), Some(gamepad_b));
+
+        let gamepad_c = world.spawn(Gamepad::default()).id();
+        world.run_system_once(assign_gamepads).unwrap();
+        assert_eq!(get_gamepad(&world, first), Some(gamepad_c));
+    }
+
+    #[test]
+    fn claimed_gamepads_move_between_players() {
+        let mut world = World::new();
+        world.init_resource::<Messages<ClaimGamepad>>();
+        let first = spawn_player(&mut world, 0);
+        let second = spawn_player(&mut world, 1);
+        let gamepad_a = world.spawn(Gamepad::default()).id();
+        let gamepad_b = world.spawn(Gamepad::default()).id();
+        world.run_system_once(assign_gamepads).unwrap();
+
+        world.write_message(ClaimGamepad {
+            player: Player(1),
+            gamepad: gamepad_a,
+        });
+        world.run_system_once(apply_gamepad_claims).unwrap();
+        world.run_system_once(assign_gamepads).unwrap();
+        assert_eq!(get_gamepad(&world, second), Some(gamepad_a));
+        assert_eq!(get_gamepad(&world, first), Some(gamepad_b));
+    }
+}
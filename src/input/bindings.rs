@@ -0,0 +1,313 @@
+use std::{collections::BTreeMap, fmt, fs, io, path::PathBuf};
+
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+
+use crate::input::{Action, Player, PlayerGamepad};
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Binding {
+    Key(KeyCode),
+    Button(GamepadButton),
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "{key:?}"),
+            Binding::Button(button) => write!(f, "Pad {button:?}"),
+        }
+    }
+}
+
+/// Bindings for one player.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(transparent)]
+pub struct Bindings(BTreeMap<Action, Vec<Binding>>);
+
+#[derive(Debug)]
+pub enum BindingError {
+    /// The binding is already used by another action.
+    Duplicate(Binding, Action),
+}
+
+impl fmt::Display for BindingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindingError::Duplicate(binding, action) => {
+                write!(f, "{binding} is already bound to {action}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BindingError {}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use Action::*;
+        use Binding::*;
+
+        Self(BTreeMap::from([
+            (
+                ShiftLeft,
+                vec![
+                    Key(KeyCode::KeyA),
+                    Key(KeyCode::ArrowLeft),
+                    Button(GamepadButton::DPadLeft),
+                ],
+            ),
+            (
+                ShiftRight,
+                vec![
+                    Key(KeyCode::KeyD),
+                    Key(KeyCode::ArrowRight),
+                    Button(GamepadButton::DPadRight),
+                ],
+            ),
+            (
+                SoftDrop,
+                vec![
+                    Key(KeyCode::KeyS),
+                    Key(KeyCode::ArrowDown),
+                    Button(GamepadButton::DPadDown),
+                ],
+            ),
+            (
+                HardDrop,
+                vec![Key(KeyCode::Space), Button(GamepadButton::DPadUp)],
+            ),
+            (
+                SonicDrop,
+                vec![Key(KeyCode::KeyQ), Button(GamepadButton::West)],
+            ),
+            (
+                FirmDrop,
+                vec![Key(KeyCode::KeyE), Button(GamepadButton::North)],
+            ),
+            (
+                RotateLeft,
+                vec![Key(KeyCode::KeyZ), Button(GamepadButton::East)],
+            ),
+            (
+                RotateRight,
+                vec![
+                    Key(KeyCode::KeyW),
+                    Key(KeyCode::ArrowUp),
+                    Button(GamepadButton::South),
+                ],
+            ),
+            (
+                Hold,
+                vec![
+                    Key(KeyCode::KeyC),
+                    Button(GamepadButton::LeftTrigger),
+                    Button(GamepadButton::RightTrigger),
+                ],
+            ),
+        ]))
+    }
+}
+
+impl Bindings {
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.0.get(&action).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    pub fn find_action(&self, binding: Binding) -> Option<Action> {
+        self.0
+            .iter()
+            .find(|(_, bindings)| bindings.contains(&binding))
+            .map(|(action, _)| *action)
+    }
+
+    pub fn bind(&mut self, action: Action, binding: Binding) -> Result<(), BindingError> {
+        match self.find_action(binding) {
+            Some(bound_action) if bound_action == action => Ok(()),
+            Some(bound_action) => Err(BindingError::Duplicate(binding, bound_action)),
+            None => {
+                self.0.entry(action).or_default().push(binding);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn clear(&mut self, action: Action) {
+        self.0.remove(&action);
+    }
+
+    /// Bindings used by more than one action, which only a hand edited file can contain.
+    pub fn get_duplicates(&self) -> Vec<BindingError> {
+        let mut seen = Bindings(BTreeMap::new());
+        let mut duplicates = Vec::new();
+        for action in Action::iter() {
+            for binding in self.get(action) {
+                if let Err(error) = seen.bind(action, *binding) {
+                    duplicates.push(error);
+                }
+            }
+        }
+        duplicates
+    }
+
+    /// Gamepad input is ignored until `assign_gamepads` gives the map a gamepad.
+    pub fn get_input_map(&self) -> InputMap<Action> {
+        let mut input_map = InputMap::default();
+        for (action, bindings) in self.0.iter() {
+            for binding in bindings {
+                match *binding {
+                    Binding::Key(key) => input_map.insert(*action, key),
+                    Binding::Button(button) => input_map.insert(*action, button),
+                };
+            }
+        }
+        input_map.set_gamepad(Entity::PLACEHOLDER);
+        input_map
+    }
+}
+
+/// Bindings per player, saved to `bindings.ron` in the user's config directory.
+#[derive(Resource, Serialize, Deserialize, Default)]
+#[serde(transparent)]
+pub struct PlayerBindings(BTreeMap<usize, Bindings>);
+
+impl PlayerBindings {
+    pub fn get(&self, player: Player) -> Bindings {
+        self.0.get(&player.0).cloned().unwrap_or_default()
+    }
+
+    pub fn set(&mut self, player: Player, bindings: Bindings) {
+        self.0.insert(player.0, bindings);
+        self.save();
+    }
+
+    pub fn load() -> Self {
+        let Some(path) = get_bindings_path() else {
+            return Self::default();
+        };
+
+        let player_bindings = match fs::read_to_string(&path) {
+            Ok(data) => ron::from_str(&data).unwrap_or_else(|error| {
+                error!("Failed to parse {}: {error}", path.display());
+                Self::default()
+            }),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(error) => {
+                error!("Failed to read {}: {error}", path.display());
+                Self::default()
+            }
+        };
+        for (player, bindings) in player_bindings.0.iter() {
+            for error in bindings.get_duplicates() {
+                warn!("Player {}: {error}", player + 1);
+            }
+        }
+        player_bindings
+    }
+
+    fn save(&self) {
+        let Some(path) = get_bindings_path() else {
+            error!("No config directory to save bindings to");
+            return;
+        };
+
+        let result = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(io::Error::other)
+            .and_then(|data| {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(&path, data)
+            });
+        if let Err(error) = result {
+            error!("Failed to save {}: {error}", path.display());
+        }
+    }
+}
+
+fn get_bindings_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("tetrominoes").join("bindings.ron"))
+}
+
+// ========== Systems ==========
+
+/// Rebuilds input maps when a player is added or any bindings change, keeping assigned gamepads.
+pub fn apply_player_bindings(
+    mut players: Query<(Ref<Player>, &mut InputMap<Action>, Option<&PlayerGamepad>)>,
+    player_bindings: Res<PlayerBindings>,
+) {
+    for (player, mut input_map, gamepad) in players.iter_mut() {
+        if !player.is_added() && !player_bindings.is_changed() {
+            continue;
+        }
+
+        *input_map = player_bindings.get(*player).get_input_map();
+        if let Some(gamepad) = gamepad {
+            input_map.set_gamepad(gamepad.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_bindings_have_no_duplicates() {
+        assert!(Bindings::default().get_duplicates().is_empty());
+    }
+
+    #[test]
+    fn binds_new_bindings() {
+        let mut bindings = Bindings(BTreeMap::new());
+        let binding = Binding::Key(KeyCode::KeyX);
+        bindings.bind(Action::RotateLeft, binding).unwrap();
+        assert_eq!(bindings.get(Action::RotateLeft), &[binding]);
+        assert_eq!(bindings.find_action(binding), Some(Action::RotateLeft));
+    }
+
+    #[test]
+    fn rebinding_to_the_same_action_is_a_no_op() {
+        let mut bindings = Bindings::default();
+        let binding = Binding::Key(KeyCode::Space);
+        bindings.bind(Action::HardDrop, binding).unwrap();
+        assert_eq!(bindings, Bindings::default());
+    }
+
+    #[test]
+    fn rejects_bindings_used_by_another_action() {
+        let mut bindings = Bindings::default();
+        let binding = Binding::Key(KeyCode::Space);
+        let error = bindings.bind(Action::Hold, binding).unwrap_err();
+        assert!(matches!(
+            error,
+            BindingError::Duplicate(duplicate, Action::HardDrop) if duplicate == binding
+        ));
+        assert!(!bindings.get(Action::Hold).contains(&binding));
+    }
+
+    #[test]
+    fn finds_duplicates_in_hand_edited_bindings() {
+        let binding = Binding::Key(KeyCode::KeyC);
+        let bindings = Bindings(BTreeMap::from([
+            (Action::Hold, vec![binding]),
+            (Action::HardDrop, vec![binding]),
+        ]));
+        let duplicates = bindings.get_duplicates();
+        assert_eq!(duplicates.len(), 1);
+        assert!(matches!(
+            duplicates[0],
+            BindingError::Duplicate(duplicate, Action::HardDrop) if duplicate == binding
+        ));
+    }
+
+    #[test]
+    fn clear_removes_every_binding_for_an_action() {
+        let mut bindings = Bindings::default();
+        bindings.clear(Action::ShiftLeft);
+        assert!(bindings.get(Action::ShiftLeft).is_empty());
+        assert_eq!(bindings.find_action(Binding::Key(KeyCode::KeyA)), None);
+    }
+}
@@ -0,0 +1,211 @@
+use bevy::prelude::*;
+use strum::{EnumCount, IntoEnumIterator};
+
+use crate::input::{
+    Action, ClaimGamepad, Player,
+    bindings::{Binding, Bindings, PlayerBindings},
+};
+
+const MENU_KEY: KeyCode = KeyCode::F1;
+
+pub struct RebindMenuPlugin;
+
+impl Plugin for RebindMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, spawn_rebind_menu).add_systems(
+            Update,
+            (
+                toggle_rebind_menu,
+                apply_rebind_menu_input,
+                update_rebind_menu_text,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Lists every action's bindings for one player. The game is paused while it's open.
+#[derive(Component, Default)]
+#[require(Text2d, Visibility::Hidden)]
+pub struct RebindMenu {
+    open: bool,
+    player: usize,
+    selected: usize,
+    waiting_for_input: bool,
+    /// Waiting for a button press on the gamepad the player wants.
+    waiting_for_gamepad: bool,
+    message: Option<String>,
+    /// Whether opening the menu paused the game, so closing it doesn't resume a paused game.
+    paused_time: bool,
+}
+
+impl RebindMenu {
+    fn selected_action(&self) -> Action {
+        Action::iter()
+            .nth(self.selected)
+            .expect("Selected index is always in range")
+    }
+}
+
+fn spawn_rebind_menu(mut commands: Commands) {
+    commands.spawn((
+        Name::new("RebindMenu"),
+        RebindMenu::default(),
+        TextLayout::new_with_justify(Justify::Left),
+        Transform::from_xyz(0.0, 0.0, 20.0),
+    ));
+}
+
+fn toggle_rebind_menu(
+    menu: Single<(&mut RebindMenu, &mut Visibility)>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    let (mut menu, mut visibility) = menu.into_inner();
+    let close = menu.open && keys.just_pressed(KeyCode::Escape);
+    if !keys.just_pressed(MENU_KEY) && !close {
+        return;
+    }
+    if menu.waiting_for_input || menu.waiting_for_gamepad {
+        return; // Escape cancels the capture instead
+    }
+
+    menu.open = !menu.open;
+    menu.message = None;
+    if menu.open {
+        *visibility = Visibility::Visible;
+        menu.paused_time = !time.is_paused();
+        time.pause();
+    } else {
+        *visibility = Visibility::Hidden;
+        if menu.paused_time {
+            time.unpause();
+        }
+    }
+}
+
+fn apply_rebind_menu_input(
+    mut menu: Single<&mut RebindMenu>,
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<(Entity, &Gamepad)>,
+    players: Query<&Player>,
+    mut player_bindings: ResMut<PlayerBindings>,
+    mut claim_messages: MessageWriter<ClaimGamepad>,
+) {
+    if !menu.open {
+        return;
+    }
+
+    let player = Player(menu.player);
+    let action = menu.selected_action();
+
+    if menu.waiting_for_gamepad {
+        if keys.just_pressed(KeyCode::Escape) {
+            menu.waiting_for_gamepad = false;
+            return;
+        }
+        if let Some((gamepad, _)) = gamepads
+            .iter()
+            .find(|(_, gamepad)| gamepad.get_just_pressed().next().is_some())
+        {
+            menu.waiting_for_gamepad = false;
+            claim_messages.write(ClaimGamepad { player, gamepad });
+            menu.message = Some(format!("Gamepad given to player {}", player.0 + 1));
+        }
+        return;
+    }
+
+    if menu.waiting_for_input {
+        if keys.just_pressed(KeyCode::Escape) {
+            menu.waiting_for_input = false;
+            return;
+        }
+
+        let binding = keys
+            .get_just_pressed()
+            .next()
+            .map(|key| Binding::Key(*key))
+            .or_else(|| {
+                gamepads
+                    .iter()
+                    .find_map(|(_, gamepad)| gamepad.get_just_pressed().next())
+                    .map(|button| Binding::Button(*button))
+            });
+        let Some(binding) = binding.filter(|binding| *binding != Binding::Key(MENU_KEY)) else {
+            return;
+        };
+
+        menu.waiting_for_input = false;
+        let mut bindings = player_bindings.get(player);
+        match bindings.bind(action, binding) {
+            Ok(()) => {
+                menu.message = None;
+                player_bindings.set(player, bindings);
+            }
+            Err(error) => menu.message = Some(error.to_string()),
+        }
+        return;
+    }
+
+    let player_count = players.iter().map(|player| player.0 + 1).max().unwrap_or(1);
+    if keys.just_pressed(KeyCode::ArrowUp) {
+        menu.selected = (menu.selected + Action::COUNT - 1) % Action::COUNT;
+    } else if keys.just_pressed(KeyCode::ArrowDown) {
+        menu.selected = (menu.selected + 1) % Action::COUNT;
+    } else if keys.just_pressed(KeyCode::ArrowLeft) {
+        menu.player = (menu.player + player_count - 1) % player_count;
+    } else if keys.just_pressed(KeyCode::ArrowRight) {
+        menu.player = (menu.player + 1) % player_count;
+    } else if keys.just_pressed(KeyCode::Enter) {
+        menu.waiting_for_input = true;
+        menu.message = None;
+    } else if keys.just_pressed(KeyCode::Backspace) {
+        let mut bindings = player_bindings.get(player);
+        bindings.clear(action);
+        player_bindings.set(player, bindings);
+    } else if keys.just_pressed(KeyCode::KeyG) {
+        menu.waiting_for_gamepad = true;
+        menu.message = None;
+    } else if keys.just_pressed(KeyCode::KeyR) {
+        player_bindings.set(player, Bindings::default());
+        menu.message = Some("Reset to defaults".to_string());
+    }
+}
+
+fn update_rebind_menu_text(
+    menu: Single<(Ref<RebindMenu>, &mut Text2d)>,
+    player_bindings: Res<PlayerBindings>,
+) {
+    let (menu, mut text) = menu.into_inner();
+    if !menu.is_changed() && !player_bindings.is_changed() {
+        return;
+    }
+    let bindings = player_bindings.get(Player(menu.player));
+
+    let mut lines = vec![format!("Controls - Player {}\n", menu.player + 1)];
+    for (index, action) in Action::iter().enumerate() {
+        let cursor = if index == menu.selected { ">" } else { " " };
+        let bound = if index == menu.selected && menu.waiting_for_input {
+            "press a key or button...".to_string()
+        } else {
+            bindings
+                .get(action)
+                .iter()
+                .map(Binding::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        lines.push(format!("{cursor} {action:<12} {bound}"));
+    }
+    lines.push(String::new());
+    if menu.waiting_for_gamepad {
+        lines.push("press a button on the gamepad to use...".to_string());
+    } else {
+        lines.push(menu.message.clone().unwrap_or_default());
+    }
+    lines.push(
+        "Enter bind  Backspace clear  R reset  G gamepad  Left/Right player  F1 close".to_string(),
+    );
+
+    text.0 = lines.join("\n");
+}
@@ -2,47 +2,124 @@ use bevy::prelude::*;
 use rand::{self, RngCore, SeedableRng};
 use rand_pcg::Pcg32;
 
+mod app_state;
 mod board;
 mod input;
+mod music;
 mod rng;
 mod tiles;
 
 use crate::{
-    board::{BoardPlugin, SpawnNextTetromino, spawn_board},
-    input::InputPlugin,
-    rng::RandomSource,
+    app_state::{AppState, AppStatePlugin, SelectedMode},
+    board::{
+        BoardPlugin, SpawnNextTetromino, board_config::BoardSettings, fumen::LoadFumen,
+        game_mode::GameMode, piece_sequence::PieceSequence, spawn_board,
+    },
+    input::{InputPlugin, Player},
+    music::MusicPlugin,
+    rng::{RandomSeed, RandomSource},
     tiles::TilePlugin,
 };
 
+/// Boards spawned side by side with `--players=`, one per local player.
+const MAX_PLAYERS: usize = 4;
+
 fn main() -> AppExit {
     App::new()
         .insert_resource(ClearColor(Color::srgb(0.1, 0.1, 0.1)))
         .add_plugins((DefaultPlugins.set(ImagePlugin::default_nearest()),))
-        .add_plugins((TilePlugin, BoardPlugin, InputPlugin))
+        .add_plugins((
+            TilePlugin,
+            BoardPlugin,
+            InputPlugin,
+            AppStatePlugin,
+            MusicPlugin,
+        ))
         .add_systems(Startup, setup)
+        .add_systems(OnEnter(AppState::Countdown), spawn_boards)
         .insert_resource(Time::<Fixed>::from_hz(60.0))
         .run()
 }
 
-fn setup(
+fn setup(mut commands: Commands) {
+    commands.spawn(Camera2d);
+    insert_random_source(&mut commands);
+
+    if let Some(mode) = get_arg("--mode=") {
+        match mode.parse::<GameMode>() {
+            Ok(mode) => commands.insert_resource(SelectedMode(mode)),
+            Err(_) => error!("Invalid game mode: {mode}"),
+        }
+    }
+}
+
+fn spawn_boards(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
-    spawn_next_messages: MessageWriter<SpawnNextTetromino>,
+    mut spawn_next_messages: MessageWriter<SpawnNextTetromino>,
+    mut load_fumen_messages: MessageWriter<LoadFumen>,
+    board_settings: Res<BoardSettings>,
+    selected_mode: Res<SelectedMode>,
 ) {
-    commands.spawn(Camera2d);
+    // Every game gets a fresh seed, so the seed saved with a personal best reproduces its pieces
+    let mut rng = insert_random_source(&mut commands);
+
+    // The command line overrides the sequence from the settings file
+    let piece_sequence = get_arg("--sequence=")
+        .and_then(|sequence| {
+            sequence
+                .parse::<PieceSequence>()
+                .inspect_err(|error| error!("Invalid piece sequence: {error}"))
+                .ok()
+        })
+        .or_else(|| board_settings.config.piece_sequence());
 
+    let player_count = get_arg("--players=")
+        .and_then(|count| {
+            count
+                .parse::<usize>()
+                .inspect_err(|error| error!("Invalid player count: {error}"))
+                .ok()
+        })
+        .unwrap_or(1)
+        .clamp(1, MAX_PLAYERS);
+    let fumen = get_arg("--fumen=");
+
+    for index in 0..player_count {
+        let board = spawn_board(
+            &mut commands,
+            board_settings.config.board_size(),
+            uvec2(8, 8),
+            &mut meshes,
+            &mut materials,
+            &mut rng,
+            piece_sequence.clone(),
+            selected_mode.0,
+            board_settings.config.clone(),
+            &mut spawn_next_messages,
+        );
+
+        commands.entity(board).insert(Player(index));
+
+        if let Some(data) = &fumen {
+            load_fumen_messages.write(LoadFumen {
+                board,
+                data: data.clone(),
+                page: 0,
+            });
+        }
+    }
+}
+
+fn insert_random_source(commands: &mut Commands) -> Pcg32 {
     let random_seed = rand::rng().next_u64();
-    let mut rng = Pcg32::seed_from_u64(random_seed);
+    let rng = Pcg32::seed_from_u64(random_seed);
     commands.insert_resource(RandomSource(rng.clone()));
+    commands.insert_resource(RandomSeed(random_seed));
+    rng
+}
 
-    spawn_board(
-        &mut commands,
-        uvec2(10, 20),
-        uvec2(8, 8),
-        &mut meshes,
-        &mut materials,
-        &mut rng,
-        spawn_next_messages,
-    );
+fn get_arg(prefix: &str) -> Option<String> {
+    std::env::args().find_map(|arg| arg.strip_prefix(prefix).map(str::to_string))
 }
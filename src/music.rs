@@ -0,0 +1,159 @@
+use bevy::{audio::Volume, platform::collections::HashMap, prelude::*};
+use strum::IntoEnumIterator;
+
+use crate::{
+    app_state::{AppState, SelectedMode},
+    board::{
+        Board,
+        game_mode::{GameMode, ModeProgress},
+        placed_tile::PlacedTile,
+        sound::{SoundCategory, SoundSettings},
+    },
+    tiles::{Tile, Tilemap},
+};
+
+/// How close to the top the stack gets before the danger track plays.
+const DANGER_ROWS: f32 = 4.0;
+const TEMPO_PER_LEVEL: f32 = 0.03;
+const MAX_TEMPO: f32 = 1.5;
+
+pub struct MusicPlugin;
+
+impl Plugin for MusicPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, load_music)
+            .add_systems(OnExit(AppState::Countdown), spawn_music)
+            .add_systems(OnEnter(AppState::Paused), pause_music)
+            .add_systems(OnExit(AppState::Paused), resume_music)
+            .add_systems(Update, update_music.run_if(in_state(AppState::Playing)));
+    }
+}
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum MusicTrack {
+    Mode(GameMode),
+    /// Plays instead of the mode's track while any stack is close to the top.
+    Danger,
+}
+
+impl MusicTrack {
+    fn path(self) -> String {
+        match self {
+            MusicTrack::Mode(mode) => format!("music/{}.wav", mode.to_string().to_lowercase()),
+            MusicTrack::Danger => "music/danger.wav".to_string(),
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct MusicTracks(pub HashMap<MusicTrack, Handle<AudioSource>>);
+
+#[derive(Component)]
+struct Music {
+    track: MusicTrack,
+}
+
+fn get_tempo(level: u32) -> f32 {
+    (1.0 + level.saturating_sub(1) as f32 * TEMPO_PER_LEVEL).min(MAX_TEMPO)
+}
+
+fn spawn_music_track(
+    commands: &mut Commands,
+    tracks: &MusicTracks,
+    settings: &SoundSettings,
+    track: MusicTrack,
+    tempo: f32,
+) {
+    let Some(source) = tracks.0.get(&track) else {
+        bevy::log::error_once!("Failed to get music track {track:?}!");
+        return;
+    };
+    commands.spawn((
+        Name::new("Music"),
+        Music { track },
+        AudioPlayer::new(source.clone()),
+        PlaybackSettings::LOOP
+            .with_volume(Volume::Linear(settings.volume(SoundCategory::Music)))
+            .with_speed(tempo),
+        DespawnOnEnter(AppState::Results),
+    ));
+}
+
+fn load_music(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let tracks = GameMode::iter()
+        .map(MusicTrack::Mode)
+        .chain([MusicTrack::Danger])
+        .map(|track| (track, asset_server.load(track.path())))
+        .collect();
+    commands.insert_resource(MusicTracks(tracks));
+}
+
+// ========== Systems ==========
+
+fn spawn_music(
+    mut commands: Commands,
+    tracks: Res<MusicTracks>,
+    settings: Res<SoundSettings>,
+    selected_mode: Res<SelectedMode>,
+) {
+    spawn_music_track(
+        &mut commands,
+        &tracks,
+        &settings,
+        MusicTrack::Mode(selected_mode.0),
+        get_tempo(1),
+    );
+}
+
+/// Speeds the music up with the highest level on any board, and switches to the danger track
+/// while a stack is close to the top.
+fn update_music(
+    mut commands: Commands,
+    music: Single<(Entity, &Music, Option<&AudioSink>)>,
+    tracks: Res<MusicTracks>,
+    settings: Res<SoundSettings>,
+    selected_mode: Res<SelectedMode>,
+    boards: Query<(&ModeProgress, &Tilemap), With<Board>>,
+    placed_tiles: Query<&Tile, With<PlacedTile>>,
+) {
+    let (music_entity, music, sink) = music.into_inner();
+
+    let level = boards
+        .iter()
+        .map(|(progress, _)| progress.level)
+        .max()
+        .unwrap_or(1);
+    let tempo = get_tempo(level);
+
+    let danger = placed_tiles.iter().any(|tile| {
+        boards
+            .get(tile.tilemap)
+            .is_ok_and(|(_, tilemap)| tile.pos.y >= tilemap.size.y as f32 - DANGER_ROWS)
+    });
+    let track = if danger {
+        MusicTrack::Danger
+    } else {
+        MusicTrack::Mode(selected_mode.0)
+    };
+
+    if track != music.track {
+        commands.entity(music_entity).despawn();
+        spawn_music_track(&mut commands, &tracks, &settings, track, tempo);
+    } else if let Some(sink) = sink
+        && sink.speed() != tempo
+    {
+        sink.set_speed(tempo);
+    }
+}
+
+fn pause_music(sinks: Query<&AudioSink, With<Music>>) {
+    for sink in sinks.iter() {
+        sink.pause();
+    }
+}
+
+fn resume_music(sinks: Query<&AudioSink, With<Music>>) {
+    for sink in sinks.iter() {
+        sink.play();
+    }
+}
@@ -2,3 +2,7 @@ use rand_pcg::Pcg32;
 
 #[derive(bevy::prelude::Resource)]
 pub struct RandomSource(pub Pcg32);
+
+/// Seed the random source was created from, recorded alongside personal bests.
+#[derive(bevy::prelude::Resource)]
+pub struct RandomSeed(pub u64);
@@ -43,7 +43,7 @@ fn update_tile_visibility(mut tiles: Query<(&Tile, &mut Visibility)>, tilemaps:
             continue;
         };
 
-        *visibility = if tilemap.is_in_bounds(tile.pos.as_ivec2()) {
+        *visibility = if tilemap.is_visible(tile.pos.as_ivec2()) {
             Visibility::Inherited
         } else {
             Visibility::Hidden
@@ -2,19 +2,36 @@ use bevy::{ecs::query::QueryFilter, prelude::*};
 
 use crate::tiles::Tile;
 
-#[derive(Component)]
+#[derive(Component, Default)]
 #[require(Transform)]
 pub struct Tilemap {
     pub size: UVec2,
     pub tile_size: UVec2,
+    /// Rows above `size` that can hold tiles.
+    pub buffer_height: u32,
+    /// How many of the lowest buffer rows are drawn.
+    pub visible_buffer_height: u32,
 }
 
 impl Tilemap {
+    pub fn total_height(&self) -> u32 {
+        self.size.y + self.buffer_height
+    }
+
     #[allow(dead_code)]
     pub fn is_in_bounds(&self, pos: IVec2) -> bool {
         pos.x >= 0 && pos.y >= 0 && pos.x < self.size.x as i32 && pos.y < self.size.y as i32
     }
 
+    /// Like `is_in_bounds`, but including the buffer.
+    pub fn is_in_buffer_bounds(&self, pos: IVec2) -> bool {
+        pos.x >= 0 && pos.y >= 0 && pos.x < self.size.x as i32 && pos.y < self.total_height() as i32
+    }
+
+    pub fn is_visible(&self, pos: IVec2) -> bool {
+        self.is_in_buffer_bounds(pos) && pos.y < (self.size.y + self.visible_buffer_height) as i32
+    }
+
     /// Be careful of float comparisons. Shouldn't be an issue on integer position values.
     #[allow(dead_code)]
     pub fn get_tiles<F: QueryFilter>(